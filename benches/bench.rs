@@ -82,6 +82,37 @@ fn bench_write_while_reading<S, W, R>(
     });
 }
 
+/// Benchmarks `write_op` while `n_writers - 1` other threads continuously write too.
+/// Measures write latency under write contention.
+fn bench_write_while_writing<S, W>(
+    c: &mut Criterion,
+    name: &str,
+    shared: &S,
+    n_writers: usize,
+    write_op: W,
+) where
+    S: Sync,
+    W: Fn(&S) + Sync,
+{
+    let started = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        for _ in 1..n_writers {
+            s.spawn(|| {
+                started.fetch_add(1, Relaxed);
+                while !stop.load(Relaxed) {
+                    write_op(shared);
+                }
+            });
+        }
+        while started.load(Relaxed) != n_writers - 1 {
+            hint::spin_loop();
+        }
+        c.bench_function(name, |b| b.iter(|| write_op(shared)));
+        stop.store(true, Relaxed);
+    });
+}
+
 /// Benchmarks `read_op` while a background thread also reads (read-read contention).
 fn bench_read_contended<S, R, O>(c: &mut Criterion, name: &str, shared: &S, read_op: R)
 where
@@ -428,6 +459,34 @@ fn lockfreecell_write_contended_8r(c: &mut Criterion) {
     );
 }
 
+fn lockfreecell_write_4w(c: &mut Criterion) {
+    let cell = LockFreeCell::new(42u32);
+    bench_write_while_writing(c, "lockfreecell_write_4w", &cell, 4, |c| {
+        c.write_discard(|x| x + 43)
+    });
+}
+
+fn lockfreecell_write_8w(c: &mut Criterion) {
+    let cell = LockFreeCell::new(42u32);
+    bench_write_while_writing(c, "lockfreecell_write_8w", &cell, 8, |c| {
+        c.write_discard(|x| x + 43)
+    });
+}
+
+fn lockfreecell_write_combined_4w(c: &mut Criterion) {
+    let cell = LockFreeCell::new(42u32);
+    bench_write_while_writing(c, "lockfreecell_write_combined_4w", &cell, 4, |c| {
+        c.write_combined(|x| *x += 43)
+    });
+}
+
+fn lockfreecell_write_combined_8w(c: &mut Criterion) {
+    let cell = LockFreeCell::new(42u32);
+    bench_write_while_writing(c, "lockfreecell_write_combined_8w", &cell, 8, |c| {
+        c.write_combined(|x| *x += 43)
+    });
+}
+
 // ============================================================================
 // SpinCell
 // ============================================================================
@@ -532,6 +591,10 @@ criterion_group!(
     lockfreecell_write,
     lockfreecell_write_contended_4r,
     lockfreecell_write_contended_8r,
+    lockfreecell_write_4w,
+    lockfreecell_write_8w,
+    lockfreecell_write_combined_4w,
+    lockfreecell_write_combined_8w,
     // SpinCell
     spincell_read,
     spincell_read_contended,
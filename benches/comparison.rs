@@ -1,5 +1,5 @@
 // Adapted from https://github.com/wyfo/hazarc/blob/main/benches/comparison.rs
-// Extended with: Mutex, ArcShift, LockFreeCell, SpinCell
+// Extended with: Mutex, ArcShift, LockFreeCell, SpinCell, AtomicArcCell
 
 use std::{
     array, hint,
@@ -15,7 +15,7 @@ use arc_swap::{ArcSwap, ArcSwapOption, Guard};
 use arcshift::ArcShift;
 use divan::Bencher;
 use hazarc::{domain::Domain, ArcBorrow, AtomicArc, AtomicOptionArc, Cache, DefaultDomain};
-use lock_free_cell::{LockFreeCell, SpinCell};
+use lock_free_cell::{AtomicArcCell, CountLayout, LockFreeCell, Packed, SpinCell, Split};
 
 // ============================================================================
 // Payload type (56 bytes instead of usize)
@@ -297,30 +297,57 @@ impl StoreBench for LockFreeCellBench {
 // ============================================================================
 
 /// SpinCell wrapper implementing LoadBench/StoreBench traits.
-struct SpinCellBench(SpinCell<Payload>);
-impl Default for SpinCellBench {
+struct SpinCellBench<L: CountLayout = Packed>(SpinCell<Payload, L>);
+impl<L: CountLayout> Default for SpinCellBench<L> {
     fn default() -> Self {
-        Self(SpinCell::new(Payload::default()))
+        Self(SpinCell::with_layout(Payload::default()))
     }
 }
-impl From<Arc<Payload>> for SpinCellBench {
+impl<L: CountLayout> From<Arc<Payload>> for SpinCellBench<L> {
     fn from(arc: Arc<Payload>) -> Self {
-        Self(SpinCell::new(*arc))
+        Self(SpinCell::with_layout(*arc))
     }
 }
-impl LoadBench for SpinCellBench {
-    type Guard<'a> = Payload;
+impl<L: CountLayout> LoadBench for SpinCellBench<L> {
+    type Guard<'a>
+        = Payload
+    where
+        Self: 'a;
     fn load(&self) -> Self::Guard<'_> {
         self.0.read(|x| *x)
     }
 }
-impl StoreBench for SpinCellBench {
+impl<L: CountLayout> StoreBench for SpinCellBench<L> {
     fn store(&self, arc: Arc<Payload>) {
         let v = *arc;
         self.0.write_discard(|x| *x = v);
     }
 }
 
+// ============================================================================
+// Trait impls: AtomicArcCell (new)
+// ============================================================================
+
+/// Loads with `load_full`, which returns an `Arc` like the ArcSwap and Hazarc guards.
+#[derive(Default)]
+struct AtomicArcCellBench(AtomicArcCell<Payload>);
+impl From<Arc<Payload>> for AtomicArcCellBench {
+    fn from(arc: Arc<Payload>) -> Self {
+        Self(AtomicArcCell::new(arc))
+    }
+}
+impl LoadBench for AtomicArcCellBench {
+    type Guard<'a> = Arc<Payload>;
+    fn load(&self) -> Self::Guard<'_> {
+        self.0.load_full()
+    }
+}
+impl StoreBench for AtomicArcCellBench {
+    fn store(&self, arc: Arc<Payload>) {
+        self.0.store(arc);
+    }
+}
+
 // ============================================================================
 // LoadSpin wrapper (from hazarc)
 // ============================================================================
@@ -567,23 +594,57 @@ fn lockfreecell_store_contended(b: Bencher, threads: usize) {
 
 #[divan::bench]
 fn spincell_load(b: Bencher) {
-    SpinCellBench::bench_load(b, false);
+    SpinCellBench::<Packed>::bench_load(b, false);
 }
 #[divan::bench]
 fn spincell_load_spin(b: Bencher) {
-    LoadSpin::<SpinCellBench>::bench_load(b, false);
+    LoadSpin::<SpinCellBench<Packed>>::bench_load(b, false);
 }
 #[divan::bench]
 fn spincell_load_contended(b: Bencher) {
-    SpinCellBench::bench_load_contended(b, false);
+    SpinCellBench::<Packed>::bench_load_contended(b, false);
 }
 #[divan::bench(args = [0, 1, 2, 4, 8, 16])]
 fn spincell_store(b: Bencher, threads: usize) {
-    SpinCellBench::bench_store(b, threads);
+    SpinCellBench::<Packed>::bench_store(b, threads);
 }
 #[divan::bench(args = [0, 1, 2, 4, 8, 16])]
 fn spincell_store_contended(b: Bencher, threads: usize) {
-    SpinCellBench::bench_store_contended(b, threads);
+    SpinCellBench::<Packed>::bench_store_contended(b, threads);
+}
+
+#[divan::bench]
+fn spincell_split_load_contended(b: Bencher) {
+    SpinCellBench::<Split>::bench_load_contended(b, false);
+}
+#[divan::bench(args = [0, 1, 2, 4, 8, 16])]
+fn spincell_split_store_contended(b: Bencher, threads: usize) {
+    SpinCellBench::<Split>::bench_store_contended(b, threads);
+}
+
+// ============================================================================
+// AtomicArcCell benchmarks (new)
+// ============================================================================
+
+#[divan::bench]
+fn atomicarccell_load(b: Bencher) {
+    AtomicArcCellBench::bench_load(b, false);
+}
+#[divan::bench]
+fn atomicarccell_load_spin(b: Bencher) {
+    LoadSpin::<AtomicArcCellBench>::bench_load(b, false);
+}
+#[divan::bench]
+fn atomicarccell_load_contended(b: Bencher) {
+    AtomicArcCellBench::bench_load_contended(b, false);
+}
+#[divan::bench(args = [0, 1, 2, 4, 8, 16])]
+fn atomicarccell_store(b: Bencher, threads: usize) {
+    AtomicArcCellBench::bench_store(b, threads);
+}
+#[divan::bench(args = [0, 1, 2, 4, 8, 16])]
+fn atomicarccell_store_contended(b: Bencher, threads: usize) {
+    AtomicArcCellBench::bench_store_contended(b, threads);
 }
 
 // ============================================================================
@@ -40,7 +40,7 @@ fn test_run<R, W>(
         for _ in 0..write_threads {
             scope.spawn(|_| {
                 for i in 0..iterations {
-                    black_box(w(i));
+                    w(black_box(i));
                 }
             });
         }
@@ -170,7 +170,7 @@ fn main() {
             for _ in 0..wt {
                 scope.spawn(|_| {
                     for i in 0..ITERATIONS {
-                        black_box(hazarc_for_cache.store(Arc::new(i)));
+                        hazarc_for_cache.store(Arc::new(black_box(i)));
                     }
                 });
             }
@@ -0,0 +1,121 @@
+use crossbeam_utils::CachePadded;
+use std::{fmt, sync::Arc};
+
+use crate::sync::{AtomicPtr, Collector, Ordering, protect};
+use crate::sz::BATCH_SIZE;
+
+/// Drops the cell's reference to a retired value.
+unsafe fn release<T>(ptr: *mut T, _collector: &Collector) {
+    drop(unsafe { Arc::from_raw(ptr) });
+}
+
+/// Holds an `Arc<T>` as the raw pointer from [`Arc::into_raw`], so storing an `Arc`
+/// allocates nothing and loads hit the value directly instead of a node around it.
+///
+/// The cell owns one strong reference to the current value. A replaced value is retired
+/// to the collector, which drops that reference once no reader can still be about to
+/// increment the count.
+pub struct AtomicArcCell<T> {
+    collector: Collector,
+    head: CachePadded<AtomicPtr<T>>,
+}
+
+impl<T> Drop for AtomicArcCell<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Arc::from_raw(self.head.load(Ordering::Acquire)) });
+    }
+}
+
+unsafe impl<T: Send + Sync> Send for AtomicArcCell<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomicArcCell<T> {}
+
+impl<T> AtomicArcCell<T> {
+    pub fn new(value: Arc<T>) -> Self {
+        Self {
+            collector: Collector::new().batch_size(BATCH_SIZE),
+            head: CachePadded::new(AtomicPtr::new(Arc::into_raw(value).cast_mut())),
+        }
+    }
+
+    #[inline]
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let guard = self.collector.enter();
+        f(unsafe { &*protect(&guard, &self.head, Ordering::Acquire) })
+    }
+
+    /// Returns a new reference to the current value.
+    #[inline]
+    pub fn load_full(&self) -> Arc<T> {
+        let guard = self.collector.enter();
+        let ptr = protect(&guard, &self.head, Ordering::Acquire);
+        // The guard keeps the cell's own reference alive, so the count is at least one.
+        unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        }
+    }
+
+    #[inline]
+    pub fn store(&self, value: Arc<T>) {
+        let old = self
+            .head
+            .swap(Arc::into_raw(value).cast_mut(), Ordering::AcqRel);
+        unsafe { self.collector.retire(old, release::<T>) };
+    }
+
+    /// Replaces the value and returns the previous one. The cell's reference to it is
+    /// retired like in [`store`](Self::store), so the caller gets a new one.
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        let old = self
+            .head
+            .swap(Arc::into_raw(value).cast_mut(), Ordering::AcqRel);
+        let ret = unsafe {
+            Arc::increment_strong_count(old);
+            Arc::from_raw(old)
+        };
+        unsafe { self.collector.retire(old, release::<T>) };
+        ret
+    }
+
+    /// Stores `new` if the cell still holds `current` (compared by pointer), otherwise
+    /// gives `new` back.
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Result<(), Arc<T>> {
+        let new = Arc::into_raw(new).cast_mut();
+        match self.head.compare_exchange(
+            Arc::as_ptr(current).cast_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        ) {
+            Ok(old) => {
+                unsafe { self.collector.retire(old, release::<T>) };
+                Ok(())
+            }
+            Err(_) => Err(unsafe { Arc::from_raw(new) }),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AtomicArcCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read(|v| f.debug_struct("AtomicArcCell").field("value", v).finish())
+    }
+}
+
+impl<T: Default> Default for AtomicArcCell<T> {
+    fn default() -> Self {
+        Self::new(Arc::default())
+    }
+}
+
+impl<T> From<Arc<T>> for AtomicArcCell<T> {
+    fn from(value: Arc<T>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> From<T> for AtomicArcCell<T> {
+    fn from(value: T) -> Self {
+        Self::new(Arc::new(value))
+    }
+}
@@ -0,0 +1,173 @@
+//! Flat combining for [`LockFreeCell::write_combined`](crate::LockFreeCell::write_combined).
+
+use std::{
+    any::Any,
+    cell::UnsafeCell,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::sync::{AtomicBool, AtomicPtr, Backoff, Ordering, const_fn};
+
+/// A waiting writer's closure, on the writer's stack until `done` is set.
+struct Request<T> {
+    call: unsafe fn(*const (), &mut T) -> bool,
+    data: *const (),
+    next: *mut Request<T>,
+    /// Whether the value the request was applied to got published; set before `done`.
+    landed: AtomicBool,
+    done: AtomicBool,
+}
+
+unsafe fn call<T, F: Fn(&mut T) -> bool>(data: *const (), value: &mut T) -> bool {
+    unsafe { (*data.cast::<F>())(value) }
+}
+
+fn request<T, F: Fn(&mut T) -> bool + Sync>(apply: &F) -> Request<T> {
+    Request {
+        call: call::<T, F>,
+        data: (apply as *const F).cast(),
+        next: ptr::null_mut(),
+        landed: AtomicBool::new(false),
+        done: AtomicBool::new(false),
+    }
+}
+
+/// Where the combiner leaves a writer's result; read by the writer once `done` is set.
+struct Slot<R>(UnsafeCell<Option<Result<R, Box<dyn Any + Send>>>>);
+unsafe impl<R: Send> Sync for Slot<R> {}
+impl<R> Slot<R> {
+    /// # Safety
+    /// Only the combiner holding the request may call this, before setting `done`.
+    unsafe fn set(&self, result: Result<R, Box<dyn Any + Send>>) {
+        unsafe { *self.0.get() = Some(result) };
+    }
+}
+
+/// The requests taken by one combiner, oldest first. Dropping it releases their writers,
+/// also when publishing panics.
+pub(crate) struct Batch<T> {
+    requests: Vec<(*const Request<T>, bool)>,
+    landed: bool,
+}
+
+impl<T> Batch<T> {
+    /// Applies every request that hasn't panicked yet. Returns `false` if one panics now,
+    /// leaving `value` half-updated; the caller then starts over from a fresh copy.
+    pub(crate) fn apply(&mut self, value: &mut T) -> bool {
+        for (request, live) in &mut self.requests {
+            if *live && !unsafe { ((**request).call)((**request).data, value) } {
+                *live = false;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Records that the value from the last [`apply`](Self::apply) was published. Without
+    /// it, writers whose results came from an unpublished attempt panic instead of returning.
+    pub(crate) fn landed(&mut self) {
+        self.landed = true;
+    }
+
+    /// Whether every request panicked, so there is nothing to publish.
+    pub(crate) fn failed(&self) -> bool {
+        self.requests.iter().all(|(_, live)| !live)
+    }
+}
+
+impl<T> Drop for Batch<T> {
+    fn drop(&mut self) {
+        for (request, _) in &self.requests {
+            unsafe { (**request).landed.store(self.landed, Ordering::Relaxed) };
+            unsafe { (**request).done.store(true, Ordering::Release) };
+        }
+    }
+}
+
+struct Unlock<'a>(&'a AtomicBool);
+impl Drop for Unlock<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+pub(crate) struct Combiner<T> {
+    pending: AtomicPtr<Request<T>>,
+    combining: AtomicBool,
+}
+
+impl<T> Combiner<T> {
+    const_fn! {
+        pub(crate) fn new() -> Self {
+            Self {
+                pending: AtomicPtr::new(ptr::null_mut()),
+                combining: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// Queues `f` and waits until some writer's `combine` has published it, becoming
+    /// that writer whenever no one else is.
+    pub(crate) fn run<R: Send>(
+        &self,
+        f: impl Fn(&mut T) -> R + Sync,
+        combine: impl Fn(&mut Batch<T>),
+    ) -> R {
+        let slot = Slot(UnsafeCell::new(None));
+        let apply = |value: &mut T| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(value)));
+            let ok = result.is_ok();
+            unsafe { slot.set(result) };
+            ok
+        };
+        let mut request = request(&apply);
+        // Only through this pointer from here on: the combiner reads it from another thread.
+        let request_ptr: *mut Request<T> = &mut request;
+
+        let mut head = self.pending.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*request_ptr).next = head };
+            match self.pending.compare_exchange_weak(
+                head,
+                request_ptr,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+
+        let backoff = Backoff::new();
+        while !unsafe { &(*request_ptr).done }.load(Ordering::Acquire) {
+            if self
+                .combining
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                let _unlock = Unlock(&self.combining);
+                let mut list = self.pending.swap(ptr::null_mut(), Ordering::Acquire);
+                let mut requests = Vec::new();
+                while !list.is_null() {
+                    requests.push((list as *const _, true));
+                    list = unsafe { (*list).next };
+                }
+                requests.reverse();
+                combine(&mut Batch {
+                    requests,
+                    landed: false,
+                });
+            } else {
+                backoff.snooze();
+            }
+        }
+        let landed = unsafe { &(*request_ptr).landed }.load(Ordering::Relaxed);
+        match slot.0.into_inner() {
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            Some(Ok(value)) if landed => value,
+            // `None`, or a result from an attempt whose value was never published.
+            _ => panic!("the writer combining this write panicked"),
+        }
+    }
+}
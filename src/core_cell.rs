@@ -0,0 +1,112 @@
+use alloc::boxed::Box;
+use core::fmt;
+use crossbeam_utils::CachePadded;
+
+use crate::reclaim::{GracePeriod, Reclaim};
+use crate::sync::{AtomicPtr, Ordering};
+
+/// `LockFreeCell` for `no_std` + `alloc`: every value lives in its own `Box` (there is no
+/// thread-local node cache to recycle them through) and replaced values go to the
+/// [`Reclaim`] backend `R`. With `std`, a seize `Collector` can stand in for the default
+/// [`GracePeriod`].
+pub struct CoreCell<T, R: Reclaim = GracePeriod> {
+    reclaim: R,
+    head: CachePadded<AtomicPtr<T>>,
+}
+
+impl<T, R: Reclaim> Drop for CoreCell<T, R> {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.head.load(Ordering::Acquire)) });
+    }
+}
+
+unsafe impl<T: Send, R: Reclaim + Send> Send for CoreCell<T, R> {}
+unsafe impl<T: Send + Sync, R: Reclaim + Sync> Sync for CoreCell<T, R> {}
+
+impl<T> CoreCell<T> {
+    pub fn new(value: T) -> Self {
+        Self::with_reclaim(value, GracePeriod::new())
+    }
+}
+
+impl<T, R: Reclaim> CoreCell<T, R> {
+    pub fn with_reclaim(value: T, reclaim: R) -> Self {
+        Self {
+            reclaim,
+            head: CachePadded::new(AtomicPtr::new(Box::into_raw(Box::new(value)))),
+        }
+    }
+
+    #[inline]
+    pub fn read<R2>(&self, f: impl FnOnce(&T) -> R2) -> R2 {
+        let guard = self.reclaim.enter();
+        f(unsafe { &*R::protect(&guard, &self.head) })
+    }
+
+    pub fn store(&self, value: T) {
+        let old = self
+            .head
+            .swap(Box::into_raw(Box::new(value)), Ordering::AcqRel);
+        unsafe { self.reclaim.retire(old) };
+    }
+
+    /// Replace the value and return a clone of the previous one.
+    pub fn swap(&self, value: T) -> T
+    where
+        T: Clone,
+    {
+        let old = self
+            .head
+            .swap(Box::into_raw(Box::new(value)), Ordering::AcqRel);
+        let ret = unsafe { &*old }.clone();
+        unsafe { self.reclaim.retire(old) };
+        ret
+    }
+
+    pub fn write_discard(&self, f: impl Fn(&T) -> T) {
+        let guard = self.reclaim.enter();
+        let mut new: Option<Box<T>> = None;
+        loop {
+            let head = R::protect(&guard, &self.head);
+            let value = f(unsafe { &*head });
+            let node = match new.take() {
+                Some(mut node) => {
+                    *node = value;
+                    node
+                }
+                None => Box::new(value),
+            };
+            let node = Box::into_raw(node);
+            match self
+                .head
+                .compare_exchange(head, node, Ordering::AcqRel, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    // A grace period waits for every guard, this one included.
+                    drop(guard);
+                    unsafe { self.reclaim.retire(head) };
+                    return;
+                }
+                Err(_) => new = Some(unsafe { Box::from_raw(node) }),
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug, R: Reclaim> fmt::Debug for CoreCell<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read(|v| f.debug_struct("CoreCell").field("value", v).finish())
+    }
+}
+
+impl<T: Default> Default for CoreCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for CoreCell<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
@@ -0,0 +1,90 @@
+//! The cells left without the `std` feature, run with
+//! `cargo test --no-default-features --lib core_tests`. The harness still links std, but the
+//! crate is built without it: parking spins, nothing is poisoned and `GracePeriod` is the
+//! only reclamation backend.
+
+use super::*;
+use std::{sync::Arc, thread};
+
+const THREADS: usize = 4;
+const ITERS: usize = 1000;
+
+fn count_up<L: CountLayout + 'static>(cell: SpinCell<u64, L>)
+where
+    L::State: Send + Sync,
+{
+    let cell = Arc::new(cell);
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                for _ in 0..ITERS {
+                    cell.write_discard(|x| *x += 1);
+                    assert!(cell.read(|x| *x) <= (THREADS * ITERS) as u64);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(cell.read(|x| *x), (THREADS * ITERS) as u64);
+}
+
+#[test]
+fn spin_cell_modes() {
+    for wait in [WaitStrategy::Spin, WaitStrategy::Park] {
+        for fairness in [
+            Fairness::ReaderPreferring,
+            Fairness::WriterPreferring,
+            Fairness::PhaseFair,
+        ] {
+            count_up(SpinCell::new(0).fairness(fairness).wait_strategy(wait));
+            count_up(
+                SpinCell::<_, Split>::with_layout(0)
+                    .fairness(fairness)
+                    .wait_strategy(wait),
+            );
+        }
+    }
+}
+
+#[test]
+fn spin_cell_guards_and_swaps() {
+    let cell = SpinCell::new(vec![1]).poisoning(true);
+    {
+        let read = cell.read_guard();
+        assert_eq!(*read, [1]);
+        assert_eq!(cell.try_write(|v| v.push(2)), Err(WouldBlock));
+    }
+    let upgradable = cell.upgradable_read();
+    upgradable.upgrade().push(2);
+    assert_eq!(cell.swap(vec![3]), [1, 2]);
+    assert_eq!(cell.replace_with(|v| vec![v[0] + 1]), [3]);
+    cell.store(vec![5]);
+    assert_eq!(cell.read(|v| v.clone()), [5]);
+    assert!(!cell.is_poisoned());
+}
+
+#[test]
+fn core_cell_grace_period() {
+    let cell = Arc::new(CoreCell::new(Vec::<u32>::new()));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                for _ in 0..ITERS {
+                    cell.write_discard(|v| [v.as_slice(), &[1]].concat());
+                    assert!(cell.read(|v| v.len()) <= THREADS * ITERS);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(cell.read(|v| v.len()), THREADS * ITERS);
+    assert_eq!(cell.swap(vec![7]).len(), THREADS * ITERS);
+    cell.store(vec![8]);
+    assert_eq!(format!("{cell:?}"), "CoreCell { value: [8] }");
+}
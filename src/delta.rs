@@ -0,0 +1,227 @@
+use crossbeam_utils::CachePadded;
+use std::{fmt, ptr};
+
+use crate::sync::{AtomicPtr, Backoff, Collector, Ordering, protect};
+use crate::sz::BATCH_SIZE;
+
+const DEFAULT_THRESHOLD: usize = 8;
+
+/// A patch for a [`DeltaCell`]. Applying it should cost about its own size, not `T`'s.
+pub trait Apply<T> {
+    fn apply(&self, target: &mut T);
+}
+
+enum Version<T, D> {
+    Base(T),
+    Delta(D),
+}
+
+struct Node<T, D> {
+    version: Version<T, D>,
+    /// The node this delta applies on top of; null for a base.
+    prev: *mut Node<T, D>,
+    /// Deltas between this node and its base.
+    depth: usize,
+}
+
+unsafe fn reclaim<T, D>(node: *mut Node<T, D>, _collector: &Collector) {
+    drop(unsafe { Box::from_raw(node) });
+}
+
+/// Copy-on-write cell whose writes are patches. `apply` publishes the delta on top of
+/// the current version without cloning `T`; readers see the base plus the chain of
+/// deltas since. The writer whose delta makes the chain longer than the threshold, or
+/// the first [`read`](Self::read) to see pending deltas, folds the chain into a new base.
+pub struct DeltaCell<T, D> {
+    collector: Collector,
+    head: CachePadded<AtomicPtr<Node<T, D>>>,
+    threshold: usize,
+}
+
+impl<T, D> Drop for DeltaCell<T, D> {
+    fn drop(&mut self) {
+        let mut node = self.head.load(Ordering::Acquire);
+        while !node.is_null() {
+            let next = unsafe { (*node).prev };
+            drop(unsafe { Box::from_raw(node) });
+            node = next;
+        }
+    }
+}
+
+unsafe impl<T: Send, D: Send> Send for DeltaCell<T, D> {}
+unsafe impl<T: Send + Sync, D: Send + Sync> Sync for DeltaCell<T, D> {}
+
+fn base<T, D>(value: T) -> *mut Node<T, D> {
+    Box::into_raw(Box::new(Node {
+        version: Version::Base(value),
+        prev: ptr::null_mut(),
+        depth: 0,
+    }))
+}
+
+/// Calls `f` with the base of the chain ending at `node` and its deltas, oldest first.
+unsafe fn with_parts<T, D, R>(node: *const Node<T, D>, f: impl FnOnce(&T, &[&D]) -> R) -> R {
+    let mut deltas = Vec::with_capacity(unsafe { (*node).depth });
+    let mut node = unsafe { &*node };
+    loop {
+        match &node.version {
+            Version::Delta(delta) => deltas.push(delta),
+            Version::Base(base) => {
+                deltas.reverse();
+                return f(base, &deltas);
+            }
+        }
+        node = unsafe { &*node.prev };
+    }
+}
+
+/// Clones the base of the chain ending at `node` and applies its deltas.
+unsafe fn fold<T: Clone, D: Apply<T>>(node: *const Node<T, D>) -> T {
+    unsafe {
+        with_parts(node, |base, deltas| {
+            let mut value = base.clone();
+            deltas.iter().for_each(|d| d.apply(&mut value));
+            value
+        })
+    }
+}
+
+impl<T, D> DeltaCell<T, D> {
+    pub fn new(value: T) -> Self {
+        Self::with_threshold(value, DEFAULT_THRESHOLD)
+    }
+
+    /// A cell that compacts once more than `threshold` deltas pile up.
+    pub fn with_threshold(value: T, threshold: usize) -> Self {
+        Self {
+            collector: Collector::new().batch_size(BATCH_SIZE),
+            head: CachePadded::new(AtomicPtr::new(base(value))),
+            threshold,
+        }
+    }
+
+    /// Calls `f` with the base value and the deltas published since, oldest first.
+    /// Nothing gets cloned and nothing is published, so this is the fast path for
+    /// readers that can look through the deltas.
+    pub fn read_parts<R>(&self, f: impl FnOnce(&T, &[&D]) -> R) -> R {
+        let guard = self.collector.enter();
+        let head = protect(&guard, &self.head, Ordering::Acquire);
+        unsafe { with_parts(head, f) }
+    }
+
+    /// Retires `head` and every node it builds on, once a new base replaced them.
+    unsafe fn retire_chain(&self, mut node: *mut Node<T, D>) {
+        while !node.is_null() {
+            let prev = unsafe { (*node).prev };
+            unsafe { self.collector.retire(node, reclaim::<T, D>) };
+            node = prev;
+        }
+    }
+
+    /// Replaces the value and drops the chain.
+    pub fn store(&self, value: T) {
+        let old = self.head.swap(base(value), Ordering::AcqRel);
+        unsafe { self.retire_chain(old) };
+    }
+}
+
+impl<T: Clone, D: Apply<T>> DeltaCell<T, D> {
+    /// Calls `f` with the current value. If deltas are pending, the reader folds them
+    /// into a clone of the base and publishes that as the new base, so the clone is paid
+    /// once per batch of writes rather than on every read; [`read_parts`](Self::read_parts)
+    /// never clones.
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let guard = self.collector.enter();
+        let head = protect(&guard, &self.head, Ordering::Acquire);
+        if unsafe { (*head).depth } == 0 {
+            let Version::Base(value) = (unsafe { &(*head).version }) else {
+                unreachable!()
+            };
+            return f(value);
+        }
+        let new = base(unsafe { fold(head) });
+        match self
+            .head
+            .compare_exchange(head, new, Ordering::AcqRel, Ordering::Relaxed)
+        {
+            Ok(_) => {
+                unsafe { self.retire_chain(head) };
+                // `new` is retired through the collector, so the guard keeps it alive.
+                let Version::Base(value) = (unsafe { &(*new).version }) else {
+                    unreachable!()
+                };
+                f(value)
+            }
+            Err(_) => {
+                let new = unsafe { Box::from_raw(new) };
+                let Version::Base(value) = &new.version else {
+                    unreachable!()
+                };
+                f(value)
+            }
+        }
+    }
+
+    pub fn apply(&self, delta: D) {
+        let guard = self.collector.enter();
+        let backoff = Backoff::new();
+        let mut node = Box::new(Node {
+            version: Version::Delta(delta),
+            prev: ptr::null_mut(),
+            depth: 0,
+        });
+        loop {
+            let head = protect(&guard, &self.head, Ordering::Acquire);
+            let depth = unsafe { (*head).depth } + 1;
+            if depth > self.threshold {
+                let Version::Delta(delta) = &node.version else {
+                    unreachable!()
+                };
+                let mut value = unsafe { fold(head) };
+                delta.apply(&mut value);
+                let new = base(value);
+                match self
+                    .head
+                    .compare_exchange(head, new, Ordering::AcqRel, Ordering::Relaxed)
+                {
+                    Ok(_) => {
+                        unsafe { self.retire_chain(head) };
+                        return;
+                    }
+                    Err(_) => drop(unsafe { Box::from_raw(new) }),
+                }
+            } else {
+                node.prev = head;
+                node.depth = depth;
+                let new = Box::into_raw(node);
+                match self
+                    .head
+                    .compare_exchange(head, new, Ordering::Release, Ordering::Relaxed)
+                {
+                    Ok(_) => return,
+                    Err(_) => node = unsafe { Box::from_raw(new) },
+                }
+            }
+            backoff.spin();
+        }
+    }
+}
+
+impl<T: Clone + fmt::Debug, D: Apply<T>> fmt::Debug for DeltaCell<T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read(|v| f.debug_struct("DeltaCell").field("value", v).finish())
+    }
+}
+
+impl<T: Default, D> Default for DeltaCell<T, D> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T, D> From<T> for DeltaCell<T, D> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    borrow::Borrow,
+    cell::Cell,
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash},
+    mem,
+    sync::Arc,
+};
+
+use crate::sz::{LockFreeCell, NodeStrategy};
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+/// One slot of a branch. Leaves sit inline, so path-copying a branch clones its
+/// sibling keys and values but shares every sub-branch.
+#[derive(Clone)]
+enum Entry<K, V> {
+    Leaf(u64, K, V),
+    /// Keys whose whole hash is equal.
+    Collision(u64, Vec<(K, V)>),
+    Branch(Arc<Branch<K, V>>),
+}
+
+#[derive(Clone)]
+struct Branch<K, V> {
+    bitmap: u32,
+    entries: Vec<Entry<K, V>>,
+}
+
+fn bit(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) & MASK)
+}
+
+impl<K, V> Branch<K, V> {
+    fn index(&self, bit: u32) -> usize {
+        (self.bitmap & (bit - 1)).count_ones() as usize
+    }
+
+    fn get<Q>(&self, hash: u64, shift: u32, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let bit = bit(hash, shift);
+        if self.bitmap & bit == 0 {
+            return None;
+        }
+        match &self.entries[self.index(bit)] {
+            Entry::Leaf(h, k, v) => (*h == hash && k.borrow() == key).then_some(v),
+            Entry::Collision(h, entries) if *h == hash => entries
+                .iter()
+                .find(|(k, _)| k.borrow() == key)
+                .map(|(_, v)| v),
+            Entry::Collision(..) => None,
+            Entry::Branch(branch) => branch.get(hash, shift + BITS, key),
+        }
+    }
+}
+
+impl<K: Eq + Clone, V: Clone> Branch<K, V> {
+    /// Two entries that share the fragment at `shift - BITS`. Hashes that differ do so
+    /// within 64 bits, so this never runs out of levels.
+    fn pair(old: Entry<K, V>, hash: u64, key: K, value: V, shift: u32) -> Entry<K, V> {
+        let old_hash = match &old {
+            Entry::Leaf(h, ..) | Entry::Collision(h, _) => *h,
+            Entry::Branch(_) => unreachable!("only leaves are split"),
+        };
+        if old_hash == hash {
+            let Entry::Leaf(_, k, v) = old else {
+                unreachable!("a collision with the same hash takes the key")
+            };
+            return Entry::Collision(hash, vec![(k, v), (key, value)]);
+        }
+        let (a, b) = (bit(old_hash, shift), bit(hash, shift));
+        let entries = if a == b {
+            vec![Self::pair(old, hash, key, value, shift + BITS)]
+        } else if a < b {
+            vec![old, Entry::Leaf(hash, key, value)]
+        } else {
+            vec![Entry::Leaf(hash, key, value), old]
+        };
+        Entry::Branch(Arc::new(Branch {
+            bitmap: a | b,
+            entries,
+        }))
+    }
+
+    fn insert(&mut self, hash: u64, shift: u32, key: K, value: V) -> Option<V> {
+        let bit = bit(hash, shift);
+        let i = self.index(bit);
+        if self.bitmap & bit == 0 {
+            self.bitmap |= bit;
+            self.entries.insert(i, Entry::Leaf(hash, key, value));
+            return None;
+        }
+        match &mut self.entries[i] {
+            Entry::Leaf(h, k, v) if *h == hash && *k == key => Some(mem::replace(v, value)),
+            Entry::Collision(h, entries) if *h == hash => {
+                match entries.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, v)) => Some(mem::replace(v, value)),
+                    None => {
+                        entries.push((key, value));
+                        None
+                    }
+                }
+            }
+            Entry::Branch(branch) => Arc::make_mut(branch).insert(hash, shift + BITS, key, value),
+            slot => {
+                let old = mem::replace(slot, Entry::Collision(hash, Vec::new()));
+                *slot = Self::pair(old, hash, key, value, shift + BITS);
+                None
+            }
+        }
+    }
+
+    /// Only called for keys that are present, so no branch gets copied in vain.
+    fn remove<Q>(&mut self, hash: u64, shift: u32, key: &Q) -> V
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let bit = bit(hash, shift);
+        let i = self.index(bit);
+        match &mut self.entries[i] {
+            Entry::Leaf(..) => {
+                self.bitmap &= !bit;
+                let Entry::Leaf(_, _, v) = self.entries.remove(i) else {
+                    unreachable!()
+                };
+                v
+            }
+            Entry::Collision(_, entries) => {
+                let j = entries.iter().position(|(k, _)| k.borrow() == key).unwrap();
+                let (_, v) = entries.swap_remove(j);
+                if entries.len() == 1 {
+                    let (k, last) = entries.pop().unwrap();
+                    self.entries[i] = Entry::Leaf(hash, k, last);
+                }
+                v
+            }
+            Entry::Branch(branch) => {
+                let branch = Arc::make_mut(branch);
+                let v = branch.remove(hash, shift + BITS, key);
+                // Pull a lone leaf up so lookups don't walk a chain of single-entry branches.
+                if let [entry] = &mut branch.entries[..]
+                    && !matches!(entry, Entry::Branch(_))
+                {
+                    let entry = mem::replace(entry, Entry::Collision(hash, Vec::new()));
+                    self.entries[i] = entry;
+                }
+                v
+            }
+        }
+    }
+}
+
+/// Persistent hash array mapped trie. Cloning is O(1) and an update copies only the
+/// branches on the path to its key, sharing the rest with the version it came from.
+///
+/// Inside a [`LockFreeCell`] (see [`HamtCell`]) every write publishes a new version;
+/// when the collector frees a retired one, only the branches no other version still
+/// shares are dropped.
+pub struct Hamt<K, V, H = RandomState> {
+    root: Arc<Branch<K, V>>,
+    len: usize,
+    hasher: H,
+}
+
+pub type HamtCell<K, V, H = RandomState> = LockFreeCell<Hamt<K, V, H>>;
+
+impl<K, V, H: Clone> Clone for Hamt<K, V, H> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            len: self.len,
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<K, V> Hamt<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V> Default for Hamt<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, H> Hamt<K, V, H> {
+    pub fn with_hasher(hasher: H) -> Self {
+        Self {
+            root: Arc::new(Branch {
+                bitmap: 0,
+                entries: Vec::new(),
+            }),
+            len: 0,
+            hasher,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            stack: vec![self.root.entries.iter()],
+            collision: [].iter(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, H: BuildHasher> Hamt<K, V, H> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.root.get(self.hasher.hash_one(key), 0, key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, H: BuildHasher> Hamt<K, V, H> {
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hasher.hash_one(&key);
+        let old = Arc::make_mut(&mut self.root).insert(hash, 0, key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        self.root.get(hash, 0, key)?;
+        self.len -= 1;
+        Some(Arc::make_mut(&mut self.root).remove(hash, 0, key))
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, H> fmt::Debug for Hamt<K, V, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K, V, H> IntoIterator for &'a Hamt<K, V, H> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// Entries of a [`Hamt`] in hash order.
+pub struct Iter<'a, K, V> {
+    stack: Vec<std::slice::Iter<'a, Entry<K, V>>>,
+    collision: std::slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.collision.next() {
+                return Some((k, v));
+            }
+            match self.stack.last_mut()?.next() {
+                None => {
+                    self.stack.pop();
+                }
+                Some(Entry::Leaf(_, k, v)) => return Some((k, v)),
+                Some(Entry::Collision(_, entries)) => self.collision = entries.iter(),
+                Some(Entry::Branch(branch)) => self.stack.push(branch.entries.iter()),
+            }
+        }
+    }
+}
+
+/// Map operations on a cell holding a [`Hamt`]. Writes clone the root, which is O(1),
+/// so a retried closure only redoes the path copy.
+impl<K, V, H, S> LockFreeCell<Hamt<K, V, H>, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    H: BuildHasher + Clone,
+    S: NodeStrategy<Hamt<K, V, H>>,
+{
+    pub fn get<Q, R>(&self, key: &Q, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.read(|map| map.get(key).map(f))
+    }
+
+    /// Inserts `value` and returns the value it replaced.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let old = Cell::new(None);
+        self.write_discard(|map| {
+            let mut map = map.clone();
+            old.set(map.insert(key.clone(), value.clone()));
+            map
+        });
+        old.into_inner()
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // A miss returns without publishing a copy that would invalidate readers' caches.
+        if !self.read(|map| map.contains_key(key)) {
+            return None;
+        }
+        let old = Cell::new(None);
+        self.write_discard(|map| {
+            let mut map = map.clone();
+            old.set(map.remove(key));
+            map
+        });
+        old.into_inner()
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+use crate::sync::const_fn;
+use crate::sz::LockFreeCell;
+use crate::tagged::Inline;
+
+/// A [`LockFreeCell`] whose first reader computes the value with `F`; later writes replace
+/// it as usual. Like `LazyLock`, but replaceable and without blocking: racing first readers
+/// may each call `F` and try to install a node in the empty head with a CAS, and the
+/// losers' values are dropped. Once a node is in, reads are the cell's usual protected load.
+pub struct LazyCell<T, F = fn() -> T> {
+    cell: LockFreeCell<T, Inline>,
+    init: F,
+}
+
+impl<T, F> LazyCell<T, F> {
+    const_fn! {
+        pub fn new(init: F) -> Self {
+            Self {
+                cell: LockFreeCell::uninit(),
+                init,
+            }
+        }
+    }
+
+    /// Installs `value`, whether or not the cell was initialized.
+    pub fn store(&self, value: T) {
+        self.cell.store(value);
+    }
+}
+
+impl<T, F: Fn() -> T> LazyCell<T, F> {
+    #[inline]
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.cell.get_or_init(&self.init, f)
+    }
+
+    pub fn write_discard(&self, f: impl Fn(&T) -> T) {
+        self.cell.get_or_init(&self.init, |_| ());
+        self.cell.write_discard(f);
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for LazyCell<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.cell.try_read(|v| match v {
+            Some(_) => f.debug_tuple("LazyCell").field(&self.cell).finish(),
+            None => f.write_str("LazyCell(<uninit>)"),
+        })
+    }
+}
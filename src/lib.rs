@@ -1,16 +1,81 @@
+#![cfg_attr(not(any(feature = "std", test, loom)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod arc;
+#[cfg(feature = "std")]
+mod combine;
+pub mod core_cell;
+#[cfg(feature = "std")]
+pub mod delta;
+#[cfg(feature = "std")]
+pub mod hamt;
+#[cfg(feature = "std")]
+pub mod lazy;
+#[cfg(feature = "std")]
+pub mod map;
+#[cfg(feature = "std")]
+pub mod pvec;
+#[cfg(feature = "std")]
+pub mod queue;
+pub mod reclaim;
+#[cfg(feature = "std")]
+pub mod shard;
+#[cfg(feature = "std")]
+pub mod stack;
+mod sync;
+#[cfg(feature = "std")]
 pub mod sz;
+#[cfg(feature = "std")]
 pub mod sz2;
+#[cfg(feature = "std")]
 pub mod sz3;
 pub mod tagged;
-pub use sz::LockFreeCell;
-pub use tagged::SpinCell;
+#[cfg(feature = "std")]
+pub use arc::AtomicArcCell;
+pub use core_cell::CoreCell;
+#[cfg(feature = "std")]
+pub use delta::{Apply, DeltaCell};
+#[cfg(feature = "std")]
+pub use hamt::{Hamt, HamtCell};
+#[cfg(feature = "std")]
+pub use lazy::LazyCell;
+#[cfg(feature = "std")]
+pub use map::LockFreeMap;
+#[cfg(feature = "std")]
+pub use pvec::{LockFreeVecCell, PersistentVec};
+#[cfg(feature = "std")]
+pub use queue::LockFreeQueue;
+pub use reclaim::{GracePeriod, Reclaim};
+#[cfg(feature = "std")]
+pub use shard::{Merge, ShardedCell, Sum};
+#[cfg(feature = "std")]
+pub use stack::LockFreeStack;
+#[cfg(feature = "std")]
+pub use sz::{Boxed, Counted, LockFreeCell, NodeStrategy, Snapshot, TlsCache};
+#[cfg(feature = "std")]
+pub use sz2::PerCellPool;
+#[cfg(feature = "std")]
+pub use sz3::PerThreadPool;
+pub use tagged::{
+    CountLayout, Fairness, Inline, Packed, SpinCell, SpinReadGuard, SpinUpgradableGuard,
+    SpinWriteGuard, Split, WaitStrategy, WouldBlock,
+};
+
+#[cfg(all(test, not(loom)))]
+mod core_tests;
+#[cfg(all(test, feature = "std", not(loom)))]
+mod lin_tests;
+#[cfg(all(test, feature = "std", loom))]
+mod loom_tests;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std", not(loom)))]
 mod tests {
     use super::*;
     use std::{
         hint::black_box,
-        sync::{Mutex, atomic::AtomicBool},
+        sync::{Barrier, Mutex, atomic::AtomicBool},
         time::{Duration, Instant},
     };
     use std::{sync::Arc, thread};
@@ -168,4 +233,1268 @@ mod tests {
         let new_result = lock_free.read(|x| *x);
         assert_eq!(new_result, 3042);
     }
+
+    fn exercise_strategy<S: NodeStrategy<String>>() {
+        let cell: LockFreeCell<String, S> = LockFreeCell::with_strategy("a".to_string());
+        assert_eq!(cell.read(|x| x.clone()), "a");
+        cell.store("b".to_string());
+        assert_eq!(cell.swap("c".to_string()), "b");
+        cell.write_discard(|x| format!("{x}d"));
+        assert_eq!(cell.read(|x| x.clone()), "cd");
+        for i in 0..100 {
+            cell.store(i.to_string());
+        }
+        assert_eq!(cell.read(|x| x.clone()), "99");
+    }
+
+    #[test]
+    fn strategies_read_store_swap() {
+        exercise_strategy::<Boxed>();
+        exercise_strategy::<TlsCache>();
+        exercise_strategy::<PerCellPool>();
+        exercise_strategy::<PerThreadPool>();
+        exercise_strategy::<Counted>();
+    }
+
+    fn concurrent_writes<S: NodeStrategy<u64> + 'static>() {
+        let cell: Arc<LockFreeCell<u64, S>> = Arc::new(LockFreeCell::with_strategy(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let c = cell.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        c.write_discard(|x| x + 1);
+                        black_box(c.read(|x| *x));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cell.read(|x| *x), 4000);
+    }
+
+    #[test]
+    fn strategies_concurrent_writes() {
+        concurrent_writes::<Boxed>();
+        concurrent_writes::<TlsCache>();
+        concurrent_writes::<PerCellPool>();
+        concurrent_writes::<PerThreadPool>();
+        concurrent_writes::<Counted>();
+    }
+
+    fn drops_every_value<S: NodeStrategy<Arc<()>>>() {
+        let tracker = Arc::new(());
+        let cell: LockFreeCell<Arc<()>, S> = LockFreeCell::with_strategy(tracker.clone());
+        for _ in 0..100 {
+            cell.store(tracker.clone());
+            cell.write_discard(|x| x.clone());
+        }
+        drop(cell);
+        assert_eq!(Arc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn strategies_drop_every_value() {
+        drops_every_value::<Boxed>();
+        drops_every_value::<TlsCache>();
+        drops_every_value::<PerCellPool>();
+        drops_every_value::<PerThreadPool>();
+        drops_every_value::<Counted>();
+    }
+
+    #[test]
+    fn spincell_read_write() {
+        let cell = SpinCell::new(42);
+        assert_eq!(cell.read(|x| *x), 42);
+        cell.write_discard(|x| *x += 100);
+        assert_eq!(cell.read(|x| *x), 142);
+
+        let cell: SpinCell<u32, Split> = SpinCell::with_layout(42);
+        cell.write_discard(|x| *x += 100);
+        assert_eq!(cell.read(|x| *x), 142);
+    }
+
+    #[test]
+    fn spincell_split_many_readers() {
+        // Every reader waits inside `read` for all the others, which needs more
+        // concurrent readers than the packed layout can hold.
+        const READERS: usize = 32;
+        let cell: Arc<SpinCell<u32, Split>> = Arc::new(SpinCell::with_layout(42));
+        let barrier = Arc::new(Barrier::new(READERS));
+        let handles: Vec<_> = (0..READERS)
+            .map(|_| {
+                let cell = cell.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    cell.read(|x| {
+                        barrier.wait();
+                        *x
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 42);
+        }
+        cell.write_discard(|x| *x = 7);
+        assert_eq!(cell.read(|x| *x), 7);
+    }
+
+    /// Longest `write_discard` while `READERS` threads keep overlapping reads going.
+    fn max_write_latency_under_reads(fairness: Fairness) -> Duration {
+        const READERS: usize = 4;
+        let cell: Arc<SpinCell<u32, Split>> = Arc::new(SpinCell::with_layout(0).fairness(fairness));
+        let stop = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..READERS)
+            .map(|_| {
+                let cell = cell.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        cell.read(|x| {
+                            thread::sleep(Duration::from_micros(200));
+                            black_box(*x)
+                        });
+                    }
+                })
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(10));
+        let mut max = Duration::ZERO;
+        for _ in 0..50 {
+            let start = Instant::now();
+            cell.write_discard(|x| *x += 1);
+            max = max.max(start.elapsed());
+        }
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for handle in readers {
+            handle.join().unwrap();
+        }
+        assert_eq!(cell.read(|x| *x), 50);
+        max
+    }
+
+    #[test]
+    fn spincell_writer_preferring_bounded_latency() {
+        let max = max_write_latency_under_reads(Fairness::WriterPreferring);
+        assert!(max < Duration::from_millis(500), "writer waited {max:?}");
+    }
+
+    #[test]
+    fn spincell_phase_fair_bounded_latency() {
+        let max = max_write_latency_under_reads(Fairness::PhaseFair);
+        assert!(max < Duration::from_millis(500), "writer waited {max:?}");
+    }
+
+    #[test]
+    fn spincell_failed_try_write_keeps_reader_turn() {
+        let cell = SpinCell::new(0u32).fairness(Fairness::PhaseFair);
+        // A reader turned away by the writer queues, so the unlock hands readers the turn.
+        cell.write_discard(|_| assert_eq!(cell.try_read(|v| *v), Err(WouldBlock)));
+        assert!(cell.reader_turn());
+        let guard = cell.read_guard();
+        assert_eq!(cell.try_write(|v| *v = 1), Err(WouldBlock));
+        assert!(cell.reader_turn());
+        drop(guard);
+        // Taking the lock ends the turn.
+        assert_eq!(cell.try_write(|v| *v = 1), Ok(()));
+        assert!(!cell.reader_turn());
+        assert_eq!(cell.read(|v| *v), 1);
+    }
+
+    #[test]
+    fn spincell_park_slow_writer() {
+        // More threads than the packed layout has reader slots, all stuck behind slow writes.
+        let cell = Arc::new(SpinCell::new(0u64).wait_strategy(WaitStrategy::Park));
+        let handles: Vec<_> = (0..16)
+            .map(|i| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        if i % 4 == 0 {
+                            cell.write_discard(|x| {
+                                thread::sleep(Duration::from_millis(1));
+                                *x += 1;
+                            });
+                        } else {
+                            black_box(cell.read(|x| *x));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cell.read(|x| *x), 80);
+    }
+
+    #[test]
+    fn spincell_park_sleeps() {
+        let cell = Arc::new(SpinCell::new(0u64).wait_strategy(WaitStrategy::Park));
+        let guard = cell.write_guard();
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.read(|x| *x))
+        };
+        // The reader gives up spinning and sleeps while the writer holds the lock.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while cell.parked() == 0 {
+            assert!(Instant::now() < deadline, "reader never parked");
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(guard);
+        assert_eq!(reader.join().unwrap(), 0);
+        assert_eq!(cell.parked(), 0);
+    }
+
+    #[test]
+    fn spincell_try_and_timeout() {
+        let cell = Arc::new(SpinCell::new(1u32));
+        assert_eq!(cell.try_read(|x| *x), Ok(1));
+        assert_eq!(cell.try_write(|x| std::mem::replace(x, 2)), Ok(1));
+
+        cell.read(|_| {
+            assert_eq!(cell.try_read(|x| *x), Ok(2));
+            assert_eq!(cell.try_write(|x| *x = 3), Err(WouldBlock));
+            let start = Instant::now();
+            let res = cell.write_timeout(Duration::from_millis(20), |x| *x = 3);
+            assert_eq!(res, Err(WouldBlock));
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        });
+        // A writer that timed out must not leave new readers blocked.
+        assert_eq!(cell.try_read(|x| *x), Ok(2));
+
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                cell.write_discard(|x| {
+                    locked_tx.send(()).unwrap();
+                    release_rx.recv().unwrap();
+                    *x = 4;
+                })
+            })
+        };
+        locked_rx.recv().unwrap();
+        assert_eq!(cell.try_read(|x| *x), Err(WouldBlock));
+        assert_eq!(cell.try_write(|x| *x), Err(WouldBlock));
+        assert_eq!(
+            cell.read_timeout(Duration::from_millis(10), |x| *x),
+            Err(WouldBlock)
+        );
+        release_tx.send(()).unwrap();
+        writer.join().unwrap();
+        assert_eq!(cell.read_timeout(Duration::from_secs(5), |x| *x), Ok(4));
+    }
+
+    #[test]
+    fn spincell_guards() {
+        let cell = SpinCell::new(vec![1, 2]);
+        {
+            let a = cell.read_guard();
+            let b = cell.read_guard();
+            assert_eq!(a.len() + b.len(), 4);
+            assert_eq!(cell.try_write(|v| v.len()), Err(WouldBlock));
+        }
+        {
+            let mut w = cell.write_guard();
+            w.push(3);
+            w.push(4);
+            assert_eq!(cell.try_read(|v| v.len()), Err(WouldBlock));
+        }
+        assert_eq!(*cell.read_guard(), vec![1, 2, 3, 4]);
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _w = cell.write_guard();
+            panic!("writer panicked");
+        }));
+        assert!(res.is_err());
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _r = cell.read_guard();
+            panic!("reader panicked");
+        }));
+        assert!(res.is_err());
+        assert_eq!(cell.try_write(|v| v.len()), Ok(4));
+    }
+
+    #[test]
+    fn spincell_upgradable_read() {
+        let cell = Arc::new(SpinCell::new(0u64));
+        {
+            let upgradable = cell.upgradable_read();
+            // Plain readers coexist with the upgrader, writers and a second upgrader do not.
+            assert_eq!(cell.try_read(|x| *x), Ok(0));
+            assert_eq!(cell.try_write(|x| *x), Err(WouldBlock));
+            assert_eq!(*upgradable, 0);
+            let mut w = upgradable.upgrade();
+            *w = 1;
+        }
+        assert_eq!(cell.read(|x| *x), 1);
+
+        // Read, decide, maybe mutate: every increment is applied exactly once.
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for _ in 0..500 {
+                        let guard = cell.upgradable_read();
+                        if *guard % 2 == 1 {
+                            *guard.upgrade() += 1;
+                        } else {
+                            drop(guard);
+                            cell.write_discard(|x| *x += 1);
+                        }
+                        black_box(cell.read(|x| *x));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cell.read(|x| *x), 4001);
+    }
+
+    fn write_discard_panic_on_retry<S: NodeStrategy<Arc<()>>>() {
+        let tracker = Arc::new(());
+        let cell: LockFreeCell<Arc<()>, S> = LockFreeCell::with_strategy(tracker.clone());
+        let calls = std::cell::Cell::new(0);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.write_discard(|x| {
+                calls.set(calls.get() + 1);
+                if calls.get() == 1 {
+                    // Makes the CAS fail so the closure runs again with a node already allocated.
+                    cell.store(tracker.clone());
+                    x.clone()
+                } else {
+                    panic!("writer panicked");
+                }
+            })
+        }));
+        assert!(res.is_err());
+        assert_eq!(calls.get(), 2);
+        cell.write_discard(|x| x.clone());
+        drop(cell);
+        assert_eq!(Arc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn strategies_write_discard_panic_safe() {
+        write_discard_panic_on_retry::<Boxed>();
+        write_discard_panic_on_retry::<TlsCache>();
+        write_discard_panic_on_retry::<PerCellPool>();
+        write_discard_panic_on_retry::<PerThreadPool>();
+        write_discard_panic_on_retry::<Counted>();
+    }
+
+    #[test]
+    fn spincell_panic_safe_and_poisoning() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        let cell = SpinCell::new(1u32);
+        assert!(catch_unwind(AssertUnwindSafe(|| cell.read(|_| panic!("reader")))).is_err());
+        assert!(
+            catch_unwind(AssertUnwindSafe(|| cell.write_discard(|_| panic!("writer")))).is_err()
+        );
+        // Neither the reader count nor the writer sentinel leaked.
+        assert_eq!(cell.try_write(|x| *x), Ok(1));
+        assert!(!cell.is_poisoned());
+
+        let cell = SpinCell::new(1u32).poisoning(true);
+        assert!(catch_unwind(AssertUnwindSafe(|| cell.read(|_| panic!("reader")))).is_err());
+        assert!(!cell.is_poisoned());
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            cell.write_discard(|x| {
+                *x = 2;
+                panic!("writer");
+            })
+        }));
+        assert!(res.is_err());
+        assert!(cell.is_poisoned());
+        assert_eq!(cell.read(|x| *x), 2);
+        cell.clear_poison();
+        assert!(!cell.is_poisoned());
+    }
+
+    fn spincell_replace_whole_value<L: CountLayout + 'static>() {
+        let cell: Arc<SpinCell<Vec<u32>, L>> = Arc::new(SpinCell::with_layout(vec![0; 16]));
+        let stop = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        cell.read(|v| assert!(v.iter().all(|x| *x == v[0])));
+                    }
+                })
+            })
+            .collect();
+        for i in 1..=200 {
+            match i % 3 {
+                0 => cell.store(vec![i; 16]),
+                1 => assert_eq!(cell.swap(vec![i; 16])[0], i - 1),
+                _ => assert_eq!(cell.replace_with(|v| vec![v[0] + 1; 16])[0], i - 1),
+            }
+        }
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for handle in readers {
+            handle.join().unwrap();
+        }
+        assert_eq!(cell.read(|v| v.clone()), vec![200; 16]);
+    }
+
+    #[test]
+    fn spincell_store_swap_replace_with() {
+        spincell_replace_whole_value::<Packed>();
+        spincell_replace_whole_value::<Split>();
+    }
+
+    #[test]
+    fn map_basic_and_grow() {
+        let map = LockFreeMap::new();
+        assert!(map.is_empty());
+        for i in 0..1000u32 {
+            assert_eq!(map.insert(i, i.to_string()), None);
+        }
+        assert_eq!(map.len(), 1000);
+        assert_eq!(map.insert(7, "seven".into()).as_deref(), Some("7"));
+        assert_eq!(map.get(&7, |v| v.clone()).as_deref(), Some("seven"));
+        assert!(map.update(&8, |v| format!("{v}!")));
+        assert!(!map.update(&5000, |v| v.clone()));
+        assert_eq!(map.get(&8, |v| v.len()), Some(2));
+        assert_eq!(map.remove(&9).as_deref(), Some("9"));
+        assert_eq!(map.remove(&9), None);
+        assert!(!map.contains_key(&9));
+        assert_eq!(map.len(), 999);
+        for i in 10..1000u32 {
+            assert_eq!(map.get(&i, |v| v.parse::<u32>().unwrap()), Some(i));
+        }
+    }
+
+    #[test]
+    fn map_grow_survives_panicking_clone() {
+        static ARMED: AtomicBool = AtomicBool::new(false);
+        #[derive(Debug, PartialEq)]
+        struct Boom(u32);
+        impl Clone for Boom {
+            fn clone(&self) -> Self {
+                assert!(!(self.0 == 0 && ARMED.load(std::sync::atomic::Ordering::Relaxed)));
+                Self(self.0)
+            }
+        }
+        // Keys are their own hash, so key 0 sits in bucket 0 and key 33 in bucket 1.
+        let map = LockFreeMap::with_hasher(nohash_hasher::BuildNoHashHasher::<u64>::default());
+        for k in 0..32 {
+            map.insert(k, Boom(k as u32));
+        }
+        ARMED.store(true, std::sync::atomic::Ordering::Relaxed);
+        // The 33rd entry triggers a grow, which panics cloning key 0's value.
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.insert(33, Boom(33));
+        }));
+        assert!(res.is_err());
+        ARMED.store(false, std::sync::atomic::Ordering::Relaxed);
+        // Bucket 0 was thawed, and the next insert grows the table for real.
+        assert_eq!(map.insert(16, Boom(160)), Some(Boom(16)));
+        assert_eq!(map.insert(48, Boom(48)), None);
+        assert_eq!(map.len(), 34);
+        assert_eq!(map.get(&0, Boom::clone), Some(Boom(0)));
+        assert_eq!(map.get(&16, Boom::clone), Some(Boom(160)));
+        assert_eq!(map.get(&33, Boom::clone), Some(Boom(33)));
+    }
+
+    #[test]
+    fn map_concurrent_writers_and_readers() {
+        let map = Arc::new(LockFreeMap::new());
+        let threads: Vec<_> = (0..4u64)
+            .map(|t| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    for i in 0..2000 {
+                        map.insert(t * 10_000 + i, i);
+                        map.update(&(t * 10_000 + i / 2), |v| v + 1);
+                        // Another thread's keys, possibly while the table grows.
+                        let other = (t + 1) % 4 * 10_000 + i;
+                        if let Some(v) = map.get(&other, |v| *v) {
+                            assert!(v >= i);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(map.len(), 8000);
+        for t in 0..4u64 {
+            for i in 0..2000 {
+                let bumps = if i < 1000 { 2 } else { 0 };
+                assert_eq!(map.get(&(t * 10_000 + i), |v| *v), Some(i + bumps));
+            }
+        }
+    }
+
+    #[test]
+    fn hamt_versions_share_and_free() {
+        let live = Arc::new(());
+        let cell = HamtCell::new(Hamt::new());
+        for i in 0..2000u32 {
+            assert_eq!(cell.insert(i, live.clone()), None);
+        }
+        let snapshot = cell.read(Hamt::clone);
+        for i in 0..1000u32 {
+            assert!(cell.remove(&i).is_some());
+        }
+        assert!(cell.insert(1500, live.clone()).is_some());
+        assert_eq!(cell.remove(&0), None);
+        // The old version is untouched by later writes.
+        assert_eq!(snapshot.len(), 2000);
+        assert!((0..2000).all(|i| snapshot.contains_key(&i)));
+        assert_eq!(cell.read(|m| m.len()), 1000);
+        assert_eq!(cell.get(&1500, |_| ()), Some(()));
+        assert_eq!(cell.get(&10, |_| ()), None);
+        let mut keys: Vec<_> = cell.read(|m| m.iter().map(|(k, _)| *k).collect());
+        keys.sort();
+        assert_eq!(keys, (1000..2000).collect::<Vec<_>>());
+        drop((cell, snapshot));
+        assert_eq!(Arc::strong_count(&live), 1);
+    }
+
+    #[test]
+    fn hamt_remove_miss_publishes_nothing() {
+        let cell = LockFreeCell::<Hamt<u32, u32>, Counted>::with_strategy(Hamt::new());
+        cell.insert(1, 1);
+        let before = cell.load_owned();
+        assert_eq!(cell.remove(&2), None);
+        assert!(Snapshot::ptr_eq(&before, &cell.load_owned()));
+        assert_eq!(cell.remove(&1), Some(1));
+        assert!(!Snapshot::ptr_eq(&before, &cell.load_owned()));
+    }
+
+    #[test]
+    fn hamt_full_hash_collisions() {
+        #[derive(Clone, Default)]
+        struct Constant;
+        impl std::hash::Hasher for Constant {
+            fn finish(&self) -> u64 {
+                7
+            }
+            fn write(&mut self, _: &[u8]) {}
+        }
+        let mut map = Hamt::with_hasher(std::hash::BuildHasherDefault::<Constant>::default());
+        for i in 0..10 {
+            assert_eq!(map.insert(i, i), None);
+        }
+        assert_eq!(map.insert(3, 30), Some(3));
+        let before = map.clone();
+        for i in 0..9 {
+            assert_eq!(map.remove(&i), Some(if i == 3 { 30 } else { i }));
+        }
+        assert_eq!(map.get(&9), Some(&9));
+        assert_eq!(map.len(), 1);
+        assert_eq!(before.get(&3), Some(&30));
+        assert_eq!(before.iter().count(), 10);
+    }
+
+    #[test]
+    fn hamt_cell_concurrent_inserts() {
+        let cell = Arc::new(HamtCell::new(Hamt::new()));
+        let threads: Vec<_> = (0..4u64)
+            .map(|t| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        cell.insert(t * 1000 + i, i);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        cell.read(|m| {
+            assert_eq!(m.len(), 2000);
+            assert!((0..4).all(|t| (0..500).all(|i| m.get(&(t * 1000 + i)) == Some(&i))));
+        });
+    }
+
+    #[test]
+    fn persistent_vec_matches_vec() {
+        let mut vec = PersistentVec::new();
+        let mut model = Vec::new();
+        let mut versions = Vec::new();
+        // Crosses several root heights on the way up and down.
+        for round in 0..3 {
+            for i in 0..40_000usize {
+                vec.push(i);
+                model.push(i);
+                if i % 997 == 0 {
+                    vec.set(i / 2, round);
+                    model[i / 2] = round;
+                    versions.push((vec.clone(), model.clone()));
+                }
+            }
+            for len in [33_000, 1057, 1056, 1024, 1000, 64, 32, 31, 1] {
+                vec.truncate(len);
+                model.truncate(len);
+                assert_eq!(vec.len(), len);
+                assert_eq!(vec.last(), model.last());
+                versions.push((vec.clone(), model.clone()));
+            }
+            assert_eq!(vec.pop(), model.pop());
+            assert!(vec.is_empty());
+        }
+        for (vec, model) in versions {
+            assert_eq!(vec.len(), model.len());
+            assert!(vec.iter().eq(&model));
+            assert_eq!(vec.get(model.len()), None);
+        }
+    }
+
+    #[test]
+    fn persistent_vec_append_relaxed() {
+        let mut seed = 0x9E37_79B9_7F4A_7C15u64;
+        let mut rand = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize % n
+        };
+        let mut vec = PersistentVec::new();
+        let mut model = Vec::new();
+        let mut versions = Vec::new();
+        for round in 0..3000 {
+            match rand(8) {
+                0..=3 => {
+                    // Pieces of every size, so seams fall at any offset.
+                    let len = [rand(40), rand(300), rand(3000)][rand(3)];
+                    let piece: PersistentVec<usize> =
+                        (0..len).map(|i| round * 10_000 + i).collect();
+                    vec.append(&piece);
+                    model.extend(piece.iter().copied());
+                }
+                4 => {
+                    let other = vec.clone();
+                    vec.append(&other);
+                    model.extend_from_within(..);
+                }
+                5 if !model.is_empty() => {
+                    let len = rand(model.len());
+                    vec.truncate(len);
+                    model.truncate(len);
+                }
+                6 if !model.is_empty() => {
+                    let i = rand(model.len());
+                    vec.set(i, usize::MAX - round);
+                    model[i] = usize::MAX - round;
+                }
+                _ => {
+                    vec.push(round);
+                    model.push(round);
+                }
+            }
+            if model.len() > 200_000 {
+                vec.truncate(1000);
+                model.truncate(1000);
+            }
+            assert_eq!(vec.len(), model.len());
+            for _ in 0..8 {
+                if !model.is_empty() {
+                    let i = rand(model.len());
+                    assert_eq!(vec.get(i), Some(&model[i]));
+                }
+            }
+            // Nodes stay at least half full on average, so each level multiplies by 16.
+            assert!(
+                16usize.pow(vec.shift() / 5) <= 32 * model.len().max(1),
+                "too deep"
+            );
+            if round % 100 == 0 {
+                assert!(vec.iter().eq(&model));
+                versions.push((vec.clone(), model.clone()));
+            }
+        }
+        // Appends never touched the versions they came from.
+        for (vec, model) in versions {
+            assert!(vec.iter().eq(&model));
+        }
+    }
+
+    #[test]
+    fn vec_cell_push_set_truncate() {
+        let cell = Arc::new(LockFreeVecCell::new(PersistentVec::new()));
+        let threads: Vec<_> = (0..4u64)
+            .map(|t| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        cell.push((t, i));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(cell.len(), 2000);
+        let snapshot = cell.read(PersistentVec::clone);
+        cell.set(0, (9, 9));
+        cell.append(&snapshot);
+        assert_eq!(cell.len(), 4000);
+        assert_eq!(cell.read(|v| v[2000 + 1]), snapshot[1]);
+        cell.truncate(10);
+        cell.read(|v| {
+            assert_eq!(v.len(), 10);
+            assert_eq!(v[0], (9, 9));
+        });
+        // Each thread's pushes stay in order.
+        for t in 0..4 {
+            let mine: Vec<_> = snapshot
+                .iter()
+                .filter(|(x, _)| *x == t)
+                .map(|p| p.1)
+                .collect();
+            assert_eq!(mine, (0..500).collect::<Vec<_>>());
+        }
+        let panicked = std::panic::catch_unwind(|| cell.set(10, (0, 0))).is_err();
+        assert!(panicked);
+        assert_eq!(cell.len(), 10);
+    }
+
+    #[test]
+    fn stack_and_queue_order() {
+        let stack = LockFreeStack::new();
+        let queue = LockFreeQueue::new();
+        assert!(stack.is_empty() && queue.is_empty());
+        for i in 0..100 {
+            stack.push(i.to_string());
+            queue.push(i.to_string());
+        }
+        for i in 0..100 {
+            assert_eq!(stack.pop(), Some((99 - i).to_string()));
+            assert_eq!(queue.pop(), Some(i.to_string()));
+        }
+        assert_eq!((stack.pop(), queue.pop()), (None, None));
+        // Leftovers are dropped with the containers.
+        let (stack, queue, live) = (LockFreeStack::new(), LockFreeQueue::new(), Arc::new(()));
+        stack.push(live.clone());
+        queue.push(live.clone());
+        queue.push(live.clone());
+        drop((stack, queue));
+        assert_eq!(Arc::strong_count(&live), 1);
+    }
+
+    #[test]
+    fn stack_and_queue_concurrent() {
+        let stack = Arc::new(LockFreeStack::new());
+        let queue = Arc::new(LockFreeQueue::new());
+        let threads: Vec<_> = (0..4u64)
+            .map(|t| {
+                let (stack, queue) = (stack.clone(), queue.clone());
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    for i in 0..1000 {
+                        stack.push(t * 1000 + i);
+                        queue.push(t * 1000 + i);
+                        popped.extend(stack.pop());
+                        popped.extend(queue.pop());
+                    }
+                    popped
+                })
+            })
+            .collect();
+        let mut popped: Vec<_> = threads
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect();
+        popped.extend(std::iter::from_fn(|| stack.pop()));
+        popped.extend(std::iter::from_fn(|| queue.pop()));
+        popped.sort();
+        let expected: Vec<_> = (0..4000).flat_map(|v| [v, v]).collect();
+        assert_eq!(popped, expected);
+    }
+
+    #[test]
+    fn atomic_arc_cell() {
+        let first = Arc::new(String::from("a"));
+        let cell = AtomicArcCell::new(first.clone());
+        let loaded = cell.load_full();
+        assert!(Arc::ptr_eq(&loaded, &first));
+        assert_eq!(cell.read(|v| v.len()), 1);
+
+        let second = Arc::new(String::from("b"));
+        assert!(cell.compare_and_swap(&first, second.clone()).is_ok());
+        let third = Arc::new(String::from("c"));
+        let back = cell.compare_and_swap(&first, third.clone()).unwrap_err();
+        assert!(Arc::ptr_eq(&back, &third));
+        assert!(Arc::ptr_eq(&cell.swap(third.clone()), &second));
+        cell.store(Arc::new(String::from("d")));
+        assert_eq!(*cell.load_full(), "d");
+        assert_eq!(format!("{cell:?}"), r#"AtomicArcCell { value: "d" }"#);
+
+        drop((cell, loaded, back));
+        assert_eq!(Arc::strong_count(&first), 1);
+        assert_eq!(Arc::strong_count(&second), 1);
+        assert_eq!(Arc::strong_count(&third), 1);
+    }
+
+    #[test]
+    fn owned_snapshots_outlive_writes() {
+        let cell = Arc::new(LockFreeCell::<String, Counted>::with_strategy("a".into()));
+        let first = cell.load_owned();
+        let again = first.clone();
+        assert!(Snapshot::ptr_eq(&first, &again));
+        cell.store("b".into());
+        cell.write_discard(|v| format!("{v}c"));
+        assert_eq!((first.as_str(), cell.load_owned().as_str()), ("a", "bc"));
+        let sent = thread::spawn(move || format!("{again:?}")).join().unwrap();
+        assert_eq!(sent, r#""a""#);
+
+        let live = Arc::new(());
+        let cell = LockFreeCell::<_, Counted>::with_strategy(live.clone());
+        let snapshots: Vec<_> = (0..10).map(|_| cell.load_owned()).collect();
+        cell.store(Arc::new(()));
+        drop(cell);
+        assert_eq!(Arc::strong_count(&live), 2);
+        drop(snapshots);
+        assert_eq!(Arc::strong_count(&live), 1);
+    }
+
+    #[test]
+    fn write_combined_returns_each_result() {
+        let cell = Arc::new(LockFreeCell::new(0u64));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    (0..1000)
+                        .map(|_| {
+                            cell.write_combined(|v| {
+                                *v += 1;
+                                *v
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut seen: Vec<_> = threads
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect();
+        seen.sort();
+        assert_eq!(seen, (1..=4000).collect::<Vec<_>>());
+        assert_eq!(cell.read(|v| *v), 4000);
+    }
+
+    #[test]
+    fn write_combined_panic_only_hits_its_caller() {
+        let cell = Arc::new(LockFreeCell::new(Vec::new()));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for i in 0..200 {
+                        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                            cell.write_combined(|v| {
+                                v.push((t, i));
+                                if t == 0 && i % 2 == 0 {
+                                    panic!("odd one out");
+                                }
+                            })
+                        }));
+                        assert_eq!(r.is_err(), t == 0 && i % 2 == 0);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        cell.read(|v| {
+            assert_eq!(v.len(), 700);
+            assert!(!v.iter().any(|&(t, i)| t == 0 && i % 2 == 0));
+        });
+    }
+
+    #[test]
+    fn write_combined_unpublished_attempt_is_not_reported() {
+        use std::sync::atomic::Ordering::Relaxed;
+        static ARMED: AtomicBool = AtomicBool::new(false);
+        #[derive(Debug)]
+        struct Touchy(u64);
+        impl Clone for Touchy {
+            fn clone(&self) -> Self {
+                assert!(!ARMED.load(Relaxed), "clone");
+                Touchy(self.0)
+            }
+        }
+
+        let cell = Arc::new(LockFreeCell::new(Touchy(0)));
+        let stored = Arc::new(AtomicBool::new(false));
+        let barrier = Arc::new(Barrier::new(2));
+        // Holds the combiner role while the two others queue up behind it, so one of them
+        // combines both.
+        let holder = {
+            let (cell, barrier) = (cell.clone(), barrier.clone());
+            thread::spawn(move || {
+                cell.write_combined(|_| {
+                    barrier.wait();
+                    thread::sleep(Duration::from_millis(100));
+                })
+            })
+        };
+        barrier.wait();
+        let writers: Vec<_> = (0..2)
+            .map(|_| {
+                let (cell, stored) = (cell.clone(), stored.clone());
+                thread::spawn(move || {
+                    cell.write_combined(|v| {
+                        // The first attempt loses its CAS to a store, and the retry panics
+                        // while copying the new head.
+                        if !stored.swap(true, Relaxed) {
+                            cell.store(Touchy(100));
+                            ARMED.store(true, Relaxed);
+                        }
+                        v.0 += 10;
+                    })
+                })
+            })
+            .collect();
+        holder.join().unwrap();
+        let returned = writers.into_iter().filter_map(|t| t.join().ok()).count();
+        ARMED.store(false, Relaxed);
+        // Every write that returned must be in the value.
+        assert_eq!(cell.read(|v| v.0), 100 + 10 * returned as u64);
+    }
+
+    #[test]
+    fn delta_cell_folds_and_compacts() {
+        struct Push(u32);
+        impl Apply<Vec<u32>> for Push {
+            fn apply(&self, target: &mut Vec<u32>) {
+                target.push(self.0);
+            }
+        }
+        let cell = DeltaCell::with_threshold(vec![0], 3);
+        for i in 1..=3 {
+            cell.apply(Push(i));
+        }
+        cell.read_parts(|base, deltas| {
+            assert_eq!(base, &[0]);
+            assert_eq!(deltas.iter().map(|d| d.0).collect::<Vec<_>>(), [1, 2, 3]);
+        });
+        // A read folds the pending deltas once and leaves the result as the new base.
+        assert_eq!(cell.read(|v| v.clone()), [0, 1, 2, 3]);
+        cell.read_parts(|base, deltas| {
+            assert_eq!(base, &[0, 1, 2, 3]);
+            assert!(deltas.is_empty());
+        });
+        // The fourth delta past that base crosses the threshold and compacts too.
+        for i in 4..=7 {
+            cell.apply(Push(i));
+        }
+        cell.read_parts(|base, deltas| {
+            assert_eq!(base, &[0, 1, 2, 3, 4, 5, 6, 7]);
+            assert!(deltas.is_empty());
+        });
+        cell.store(vec![9]);
+        cell.apply(Push(10));
+        assert_eq!(format!("{cell:?}"), "DeltaCell { value: [9, 10] }");
+    }
+
+    #[test]
+    fn delta_cell_concurrent_apply() {
+        struct Add(u64);
+        impl Apply<u64> for Add {
+            fn apply(&self, target: &mut u64) {
+                *target += self.0;
+            }
+        }
+        let cell = Arc::new(DeltaCell::with_threshold(0u64, 4));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        cell.apply(Add(1));
+                        black_box(cell.read(|v| *v));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(cell.read(|v| *v), 4000);
+        cell.read_parts(|_, deltas| assert!(deltas.len() <= 4));
+    }
+
+    #[test]
+    fn sharded_counter() {
+        let cell = Arc::new(ShardedCell::<u64>::with_shards(3, || 0));
+        assert_eq!(cell.shards(), 4);
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        cell.write(|n| n + 1);
+                    }
+                    black_box(cell.read_merged(|n| *n));
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(cell.read_merged(|n| *n), 8000);
+    }
+
+    #[test]
+    fn sharded_custom_merge() {
+        struct Max;
+        impl Merge<u32> for Max {
+            fn merge(total: &mut u32, shard: &u32) {
+                *total = (*total).max(*shard);
+            }
+        }
+        let cell = ShardedCell::<u32, Max>::new();
+        cell.write(|_| 7);
+        thread::scope(|s| {
+            s.spawn(|| cell.write(|n| *n.max(&5)));
+        });
+        assert_eq!(cell.read_merged(|n| *n), 7);
+        let drops = Arc::new(());
+        let cell = ShardedCell::<Vec<Arc<()>>, Concat>::with_shards(2, Vec::new);
+        struct Concat;
+        impl Merge<Vec<Arc<()>>> for Concat {
+            fn merge(total: &mut Vec<Arc<()>>, shard: &Vec<Arc<()>>) {
+                total.extend(shard.iter().cloned());
+            }
+        }
+        cell.write(|v| [v.clone(), vec![drops.clone()]].concat());
+        assert_eq!(cell.read_merged(|v| v.len()), 1);
+        drop(cell);
+        assert_eq!(Arc::strong_count(&drops), 1);
+    }
+
+    #[test]
+    fn core_cell_backends() {
+        fn exercise<R: Reclaim + Send + Sync + 'static>(cell: CoreCell<Vec<u32>, R>) {
+            let cell = Arc::new(cell);
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    let cell = cell.clone();
+                    thread::spawn(move || {
+                        for _ in 0..ITERS {
+                            cell.write_discard(|v| [v.as_slice(), &[1]].concat());
+                            black_box(cell.read(|v| v.len()));
+                        }
+                    })
+                })
+                .collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+            assert_eq!(cell.read(|v| v.len()), 4 * ITERS);
+            assert_eq!(cell.swap(vec![7]).len(), 4 * ITERS);
+            cell.store(vec![8]);
+            assert_eq!(format!("{cell:?}"), "CoreCell { value: [8] }");
+        }
+        exercise(CoreCell::new(Vec::new()));
+        exercise(CoreCell::with_reclaim(Vec::new(), seize::Collector::new()));
+    }
+
+    #[test]
+    fn const_cells_in_statics() {
+        static COUNTER: LockFreeCell<u64, Inline> = LockFreeCell::new_const(0);
+        static NAMES: SpinCell<Vec<&str>, Inline> = SpinCell::new_const(Vec::new());
+        assert_eq!(COUNTER.read(|n| *n), 0);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..ITERS {
+                        COUNTER.write_discard(|n| n + 1);
+                    }
+                    NAMES.write_discard(|v| v.push("t"));
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(COUNTER.read(|n| *n), 4 * ITERS as u64);
+        assert_eq!(format!("{COUNTER:?}"), "LockFreeCell { value: 4000 }");
+        assert_eq!(NAMES.read(|v| v.len()), 4);
+        assert_eq!(NAMES.swap(vec!["boxed"]).len(), 4);
+        assert_eq!(NAMES.read(|v| v.clone()), ["boxed"]);
+
+        // Cells built at run time pay nothing for the inline slot.
+        assert_eq!(
+            std::mem::size_of::<SpinCell<[u64; 16]>>(),
+            std::mem::size_of::<SpinCell<u8>>()
+        );
+        assert_eq!(
+            std::mem::size_of::<LockFreeCell<[u64; 16]>>(),
+            std::mem::size_of::<LockFreeCell<u8>>()
+        );
+
+        // A panicking first write leaves the inline value in place.
+        let cell = LockFreeCell::new_const(1);
+        let res = std::panic::catch_unwind(|| cell.write_discard(|_| panic!("first write")));
+        assert!(res.is_err());
+        assert_eq!(cell.read(|n| *n), 1);
+        assert_eq!(cell.swap(2), 1);
+        cell.write_combined(|n| *n += 1);
+        assert_eq!(cell.read(|n| *n), 3);
+
+        // Inline values are dropped exactly once, whether or not they were replaced.
+        let tracker = Arc::new(());
+        drop(LockFreeCell::new_const(tracker.clone()));
+        drop(SpinCell::new_const(tracker.clone()));
+        let cell = LockFreeCell::new_const(tracker.clone());
+        assert!(Arc::ptr_eq(&cell.swap(Arc::new(())), &tracker));
+        cell.store(Arc::new(()));
+        drop(cell);
+        let cell = LockFreeCell::<_, Inline>::with_strategy(tracker.clone());
+        cell.store(tracker.clone());
+        drop(cell);
+        let cell = SpinCell::new_const(tracker.clone());
+        cell.write_discard(|a| *a = a.clone());
+        drop(cell.replace_with(|_| Arc::new(())));
+        drop(cell);
+        let cell = SpinCell::<_, Inline>::with_layout(tracker.clone());
+        cell.store(tracker.clone());
+        drop(cell);
+        assert_eq!(Arc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn lazy_first_readers_race() {
+        let tracker = Arc::new(());
+        let inits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let cell = Arc::new(LazyCell::new({
+            let (tracker, inits) = (tracker.clone(), inits.clone());
+            move || {
+                inits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                tracker.clone()
+            }
+        }));
+        assert_eq!(format!("{cell:?}"), "LazyCell(<uninit>)");
+        let barrier = Arc::new(Barrier::new(8));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (cell, barrier) = (cell.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    cell.read(|a| Arc::as_ptr(a) as usize)
+                })
+            })
+            .collect();
+        let seen: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(seen.iter().all(|p| *p == seen[0]));
+        let inits = inits.load(std::sync::atomic::Ordering::Relaxed);
+        assert!((1..=8).contains(&inits));
+        // Losers' values are gone: only `init` and the installed value hold a clone.
+        assert_eq!(Arc::strong_count(&tracker), 3);
+        drop(cell);
+        assert_eq!(Arc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn lazy_cell_static() {
+        static PRIMES: LazyCell<Vec<u32>> = LazyCell::new(|| vec![2, 3, 5]);
+        assert_eq!(PRIMES.read(|v| v.len()), 3);
+        PRIMES.write_discard(|v| v.iter().copied().chain([7]).collect());
+        assert_eq!(PRIMES.read(|v| v.clone()), [2, 3, 5, 7]);
+        PRIMES.store(Vec::new());
+        assert!(PRIMES.read(|v| v.is_empty()));
+
+        let cell = LazyCell::new(|| 1);
+        assert_eq!(format!("{cell:?}"), "LazyCell(<uninit>)");
+        cell.write_discard(|n| n + 1);
+        assert_eq!(cell.read(|n| *n), 2);
+        assert_eq!(format!("{cell:?}"), "LazyCell(LockFreeCell { value: 2 })");
+    }
+
+    #[test]
+    fn std_traits() {
+        #[derive(Debug, Default, Clone, PartialEq)]
+        struct Holder {
+            cell: LockFreeCell<u32, PerCellPool>,
+            spin: SpinCell<String, Split>,
+        }
+        let a = Holder::default();
+        assert_eq!(
+            format!("{a:?}"),
+            r#"Holder { cell: LockFreeCell { value: 0 }, spin: SpinCell { value: "", poisoned: false } }"#
+        );
+        let b = a.clone();
+        assert_eq!(a, b);
+        b.cell.store(1);
+        b.spin.store("x".into());
+        assert_ne!(a, b);
+        assert_eq!(a.cell.read(|v| *v), 0);
+        assert_eq!(
+            b,
+            Holder {
+                cell: 1.into(),
+                spin: String::from("x").into(),
+            }
+        );
+
+        let spin = SpinCell::<f64>::from(1.5).fairness(Fairness::PhaseFair);
+        let guard = spin.write_guard();
+        assert_eq!(
+            format!("{:?}", spin),
+            "SpinCell { value: <locked>, poisoned: false }"
+        );
+        drop(guard);
+        assert_eq!(spin.clone(), spin);
+        assert_ne!(
+            LockFreeCell::<f64, Boxed>::from(f64::NAN),
+            LockFreeCell::from(f64::NAN)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Config {
+            name: LockFreeCell<String>,
+            pooled: LockFreeCell<Vec<u32>, PerCellPool>,
+            per_thread: LockFreeCell<u64, PerThreadPool>,
+            spin: SpinCell<(bool, i8)>,
+            split: SpinCell<Option<String>, Split>,
+        }
+        let config = Config {
+            name: LockFreeCell::new("a".into()),
+            pooled: LockFreeCell::with_strategy(vec![1, 2]),
+            per_thread: LockFreeCell::with_strategy(3),
+            spin: SpinCell::new((true, -4)),
+            split: SpinCell::with_layout(None),
+        };
+        config.name.store("b".into());
+        config.split.store(Some("c".into()));
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"b","pooled":[1,2],"per_thread":3,"spin":[true,-4],"split":"c"}"#
+        );
+        let back: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.name.read(|v| v.clone()), "b");
+        assert_eq!(back.pooled.read(|v| v.clone()), [1, 2]);
+        assert_eq!(back.per_thread.read(|v| *v), 3);
+        assert_eq!(back.spin.read(|v| *v), (true, -4));
+        assert_eq!(back.split.read(|v| v.clone()).as_deref(), Some("c"));
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    }
 }
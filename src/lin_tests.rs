@@ -0,0 +1,259 @@
+//! Randomized stress runs checked for linearizability against a sequential register.
+//!
+//! Every round runs a few threads doing random operations with random pauses between
+//! them, records when each call started and returned, and searches for a sequential
+//! order that respects real time and explains every result (Wing & Gong, with the
+//! memoization from Lowe). `LIN_ROUNDS` and `LIN_SEED` make runs longer or reproducible.
+
+use super::*;
+use std::{
+    collections::HashSet,
+    hint,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+const THREADS: usize = 4;
+const OPS_PER_THREAD: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Read,
+    Store(u64),
+    Add(u64),
+    Swap(u64),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Event {
+    op: Op,
+    /// What `Read` and `Swap` returned.
+    ret: u64,
+    call: u64,
+    done: u64,
+}
+
+/// The cell operations under test, as a register of `u64`. `pause` runs inside the
+/// closures, to stretch the windows in which other threads can interfere.
+trait Register: Send + Sync {
+    fn read(&self, pause: &dyn Fn()) -> u64;
+    fn store(&self, value: u64);
+    fn add(&self, delta: u64, pause: &dyn Fn());
+    fn swap(&self, value: u64) -> u64;
+}
+
+impl<S: NodeStrategy<u64>> Register for LockFreeCell<u64, S> {
+    fn read(&self, pause: &dyn Fn()) -> u64 {
+        LockFreeCell::read(self, |v| {
+            pause();
+            *v
+        })
+    }
+    fn store(&self, value: u64) {
+        LockFreeCell::store(self, value)
+    }
+    fn add(&self, delta: u64, pause: &dyn Fn()) {
+        self.write_discard(|v| {
+            pause();
+            v + delta
+        })
+    }
+    fn swap(&self, value: u64) -> u64 {
+        LockFreeCell::swap(self, value)
+    }
+}
+
+impl<L: CountLayout> Register for SpinCell<u64, L> {
+    fn read(&self, pause: &dyn Fn()) -> u64 {
+        SpinCell::read(self, |v| {
+            pause();
+            *v
+        })
+    }
+    fn store(&self, value: u64) {
+        SpinCell::store(self, value)
+    }
+    fn add(&self, delta: u64, pause: &dyn Fn()) {
+        self.write_discard(|v| {
+            pause();
+            *v += delta
+        })
+    }
+    fn swap(&self, value: u64) -> u64 {
+        SpinCell::swap(self, value)
+    }
+}
+
+struct XorShift(u64);
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Runs one round on `cell`, which must start at 0, and returns its history.
+fn record(cell: Arc<dyn Register>, seed: u64) -> Vec<Event> {
+    let clock = Arc::new(AtomicU64::new(0));
+    let ready = Arc::new(AtomicUsize::new(0));
+    let history = Arc::new(Mutex::new(Vec::new()));
+    let handles: Vec<_> = (0..THREADS as u64)
+        .map(|t| {
+            let (cell, clock, ready, history) =
+                (cell.clone(), clock.clone(), ready.clone(), history.clone());
+            thread::spawn(move || {
+                let mut rng = XorShift(seed ^ (t + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1);
+                let mut events = Vec::with_capacity(OPS_PER_THREAD);
+                // Not a `Barrier`: its wake-ups are far enough apart for each thread to
+                // finish before the next one starts.
+                ready.fetch_add(1, Ordering::SeqCst);
+                while ready.load(Ordering::SeqCst) < THREADS {
+                    thread::yield_now();
+                }
+                for i in 0..OPS_PER_THREAD as u64 {
+                    // Values are unique per history, so a read pins down which write it saw.
+                    let value = 1 + t * 1000 + i;
+                    let op = match rng.next() % 4 {
+                        0 => Op::Read,
+                        1 => Op::Store(value),
+                        2 => Op::Add(value),
+                        _ => Op::Swap(value),
+                    };
+                    // Yielding lets other threads in even on a single core.
+                    let spins = rng.next() % 4 * 32;
+                    let pause = || match spins {
+                        0 => thread::yield_now(),
+                        n => (0..n).for_each(|_| hint::spin_loop()),
+                    };
+                    pause();
+                    let call = clock.fetch_add(1, Ordering::SeqCst);
+                    let ret = match op {
+                        Op::Read => cell.read(&pause),
+                        Op::Store(v) => {
+                            cell.store(v);
+                            0
+                        }
+                        Op::Add(v) => {
+                            cell.add(v, &pause);
+                            0
+                        }
+                        Op::Swap(v) => cell.swap(v),
+                    };
+                    let done = clock.fetch_add(1, Ordering::SeqCst);
+                    events.push(Event {
+                        op,
+                        ret,
+                        call,
+                        done,
+                    });
+                }
+                history.lock().unwrap().extend(events);
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+    Arc::try_unwrap(history).unwrap().into_inner().unwrap()
+}
+
+/// Applies `event` to the sequential register, or returns `None` if its result
+/// cannot come from `state`.
+fn step(state: u64, event: &Event) -> Option<u64> {
+    match event.op {
+        Op::Read => (event.ret == state).then_some(state),
+        Op::Store(v) => Some(v),
+        Op::Add(v) => Some(state.wrapping_add(v)),
+        Op::Swap(v) => (event.ret == state).then_some(v),
+    }
+}
+
+fn linearizable(history: &[Event], initial: u64) -> bool {
+    assert!(history.len() <= 64);
+    fn search(history: &[Event], done: u64, state: u64, seen: &mut HashSet<(u64, u64)>) -> bool {
+        if done.count_ones() as usize == history.len() {
+            return true;
+        }
+        if !seen.insert((done, state)) {
+            return false;
+        }
+        let pending = || (0..history.len()).filter(|&i| done & (1 << i) == 0);
+        // Only operations invoked before every pending one returned can go next.
+        let first_return = pending().map(|i| history[i].done).min().unwrap();
+        pending()
+            .filter(|&i| history[i].call < first_return)
+            .any(|i| {
+                step(state, &history[i])
+                    .is_some_and(|next| search(history, done | (1 << i), next, seen))
+            })
+    }
+    search(history, 0, initial, &mut HashSet::new())
+}
+
+fn check(make: impl Fn() -> Arc<dyn Register>) {
+    let rounds = env_or("LIN_ROUNDS", 100);
+    let seed = env_or("LIN_SEED", 0x5EED);
+    for round in 0..rounds {
+        let history = record(
+            make(),
+            seed.wrapping_add(round).wrapping_mul(0x2545_F491_4F6C_DD1D) | 1,
+        );
+        assert!(
+            linearizable(&history, 0),
+            "not linearizable (LIN_SEED={seed}, round {round}): {history:#?}"
+        );
+    }
+}
+
+#[test]
+fn checker_rejects_stale_read() {
+    let event = |op, ret, call, done| Event {
+        op,
+        ret,
+        call,
+        done,
+    };
+    // The read starts after the store returned but still sees the old value.
+    let stale = [event(Op::Store(1), 0, 0, 1), event(Op::Read, 0, 2, 3)];
+    assert!(!linearizable(&stale, 0));
+    // Overlapping, the read may take effect first.
+    let overlapping = [event(Op::Store(1), 0, 0, 3), event(Op::Read, 0, 1, 2)];
+    assert!(linearizable(&overlapping, 0));
+    // Two swaps cannot both return the initial value.
+    let lost = [event(Op::Swap(1), 0, 0, 2), event(Op::Swap(2), 0, 1, 3)];
+    assert!(!linearizable(&lost, 0));
+}
+
+#[test]
+fn lock_free_cells_linearizable() {
+    check(|| Arc::new(LockFreeCell::<u64, Boxed>::with_strategy(0)));
+    check(|| Arc::new(LockFreeCell::<u64, TlsCache>::with_strategy(0)));
+    check(|| Arc::new(LockFreeCell::<u64, PerCellPool>::with_strategy(0)));
+    check(|| Arc::new(LockFreeCell::<u64, PerThreadPool>::with_strategy(0)));
+    check(|| Arc::new(LockFreeCell::<u64, Counted>::with_strategy(0)));
+}
+
+#[test]
+fn spin_cells_linearizable() {
+    for fairness in [
+        Fairness::ReaderPreferring,
+        Fairness::WriterPreferring,
+        Fairness::PhaseFair,
+    ] {
+        check(|| Arc::new(SpinCell::<u64, Packed>::with_layout(0).fairness(fairness)));
+        check(|| Arc::new(SpinCell::<u64, Split>::with_layout(0).fairness(fairness)));
+    }
+    check(|| Arc::new(SpinCell::new(0u64).wait_strategy(WaitStrategy::Park)));
+}
@@ -0,0 +1,424 @@
+//! Interleaving models, run with
+//! `RUSTFLAGS="--cfg loom" cargo test --release --lib loom_tests`.
+
+use super::*;
+use loom::{sync::Arc, thread};
+
+fn model(f: impl Fn() + Send + Sync + 'static) {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound.get_or_insert(3);
+    builder.check(f);
+}
+
+fn read_store_write_discard<S: NodeStrategy<u64> + 'static>() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<u64, S>::with_strategy(0));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let first = cell.read(|v| *v);
+                let second = cell.read(|v| *v);
+                assert!([0, 1, 10, 11].contains(&first));
+                assert!([0, 1, 10, 11].contains(&second));
+            })
+        };
+        let storer = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.store(10))
+        };
+        cell.write_discard(|v| v + 1);
+        reader.join().unwrap();
+        storer.join().unwrap();
+        assert!([10, 11].contains(&cell.read(|v| *v)));
+    });
+}
+
+#[test]
+fn lock_free_read_store_write_discard() {
+    read_store_write_discard::<Boxed>();
+    read_store_write_discard::<TlsCache>();
+    read_store_write_discard::<PerCellPool>();
+    read_store_write_discard::<PerThreadPool>();
+    read_store_write_discard::<Counted>();
+}
+
+fn no_lost_updates<S: NodeStrategy<u64> + 'static>() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<u64, S>::with_strategy(0));
+        let other = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.write_discard(|v| v + 1))
+        };
+        let old = cell.swap(5);
+        other.join().unwrap();
+        let now = cell.read(|v| *v);
+        assert!((old == 0 && now == 6) || (old == 1 && now == 5));
+    });
+}
+
+#[test]
+fn lock_free_no_lost_updates() {
+    no_lost_updates::<Boxed>();
+    no_lost_updates::<PerCellPool>();
+    no_lost_updates::<PerThreadPool>();
+}
+
+/// A reader racing stores that recycle nodes: a node may only be refilled after
+/// every reader that could see it has left.
+fn slot_reuse<S: NodeStrategy<String> + 'static>() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<String, S>::with_strategy("0".into()));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let seen = cell.read(|v| v.clone());
+                assert!(["0", "1", "2"].contains(&seen.as_str()));
+            })
+        };
+        cell.store("1".into());
+        cell.store("2".into());
+        reader.join().unwrap();
+        assert_eq!(cell.read(|v| v.clone()), "2");
+    });
+}
+
+#[test]
+fn pools_reuse_slots() {
+    slot_reuse::<PerCellPool>();
+    slot_reuse::<PerThreadPool>();
+}
+
+#[test]
+fn per_thread_pool_release_from_other_thread() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<u64, PerThreadPool>::with_strategy(0));
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                cell.store(1);
+                cell.store(2);
+            })
+        };
+        cell.store(3);
+        writer.join().unwrap();
+        assert!([2, 3].contains(&cell.read(|v| *v)));
+    });
+}
+
+/// Readers must never see the two halves disagree while a writer updates them.
+fn spin_reader_writer<L: CountLayout + 'static>(fairness: Fairness, wait: WaitStrategy) {
+    model(move || {
+        let cell = Arc::new(
+            SpinCell::<(u64, u64), L>::with_layout((0, 0))
+                .fairness(fairness)
+                .wait_strategy(wait),
+        );
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let (a, b) = cell.read(|v| *v);
+                assert_eq!(a, b);
+            })
+        };
+        cell.write_discard(|v| {
+            v.0 += 1;
+            v.1 += 1;
+        });
+        reader.join().unwrap();
+        assert_eq!(cell.read(|v| *v), (1, 1));
+    });
+}
+
+#[test]
+fn spincell_reader_writer() {
+    for fairness in [
+        Fairness::ReaderPreferring,
+        Fairness::WriterPreferring,
+        Fairness::PhaseFair,
+    ] {
+        spin_reader_writer::<Packed>(fairness, WaitStrategy::Spin);
+        spin_reader_writer::<Split>(fairness, WaitStrategy::Spin);
+    }
+    spin_reader_writer::<Packed>(Fairness::WriterPreferring, WaitStrategy::Park);
+}
+
+#[test]
+fn spincell_writers_exclude_each_other() {
+    model(|| {
+        let cell = Arc::new(SpinCell::new(0u64));
+        let other = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.write_discard(|v| *v += 1))
+        };
+        cell.write_discard(|v| *v += 1);
+        other.join().unwrap();
+        assert_eq!(cell.read(|v| *v), 2);
+    });
+}
+
+#[test]
+fn spincell_try_write_against_reader() {
+    model(|| {
+        let cell = Arc::new(SpinCell::new(0u64));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.read(|v| *v))
+        };
+        let wrote = cell.try_write(|v| *v = 1).is_ok();
+        let seen = reader.join().unwrap();
+        assert!(seen == 0 || (wrote && seen == 1));
+        assert_eq!(cell.read(|v| *v), wrote as u64);
+    });
+}
+
+#[test]
+fn spincell_replace_with_against_reader() {
+    model(|| {
+        let cell = Arc::new(SpinCell::new(String::from("a")));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let seen = cell.read(|v| v.clone());
+                assert!(seen == "a" || seen == "ab");
+            })
+        };
+        let old = cell.replace_with(|v| format!("{v}b"));
+        assert_eq!(old, "a");
+        reader.join().unwrap();
+        assert_eq!(cell.read(|v| v.clone()), "ab");
+    });
+}
+
+#[test]
+fn stack_push_pop() {
+    model(|| {
+        let stack = Arc::new(LockFreeStack::new());
+        stack.push(0);
+        let popper = {
+            let stack = stack.clone();
+            thread::spawn(move || stack.pop())
+        };
+        stack.push(1);
+        let mine = stack.pop();
+        let theirs = popper.join().unwrap();
+        let mut all = [mine, theirs, stack.pop()];
+        all.sort();
+        assert_eq!(all, [None, Some(0), Some(1)]);
+    });
+}
+
+#[test]
+fn queue_push_pop() {
+    model(|| {
+        let queue = Arc::new(LockFreeQueue::new());
+        let pusher = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                queue.push(1);
+                queue.push(2);
+            })
+        };
+        let first = queue.pop();
+        pusher.join().unwrap();
+        let rest: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        match first {
+            None => assert_eq!(rest, [1, 2]),
+            Some(v) => {
+                assert_eq!(v, 1);
+                assert_eq!(rest, [2]);
+            }
+        }
+    });
+}
+
+#[test]
+fn atomic_arc_load_full_against_store() {
+    model(|| {
+        let cell = Arc::new(AtomicArcCell::from(0u64));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let loaded = cell.load_full();
+                assert!([0, 1, 2].contains(&*loaded));
+            })
+        };
+        cell.store(std::sync::Arc::new(1));
+        cell.store(std::sync::Arc::new(2));
+        reader.join().unwrap();
+        assert_eq!(*cell.load_full(), 2);
+    });
+}
+
+#[test]
+fn snapshot_outlives_store() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<String, Counted>::with_strategy("a".into()));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.load_owned())
+        };
+        cell.store("b".into());
+        cell.store("c".into());
+        let snapshot = reader.join().unwrap();
+        assert!(["a", "b", "c"].contains(&snapshot.as_str()));
+        drop(cell);
+        assert!(["a", "b", "c"].contains(&snapshot.as_str()));
+    });
+}
+
+#[test]
+fn write_combined_no_lost_updates() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::new(0u64));
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.write_combined(|v| std::mem::replace(v, *v + 1)))
+        };
+        let mine = cell.write_combined(|v| std::mem::replace(v, *v + 1));
+        let theirs = writer.join().unwrap();
+        let mut seen = [mine, theirs];
+        seen.sort();
+        assert_eq!(seen, [0, 1]);
+        assert_eq!(cell.read(|v| *v), 2);
+    });
+}
+
+#[test]
+fn delta_cell_compaction_against_reader() {
+    struct Add(u64);
+    impl Apply<u64> for Add {
+        fn apply(&self, target: &mut u64) {
+            *target += self.0;
+        }
+    }
+    model(|| {
+        let cell = Arc::new(DeltaCell::with_threshold(0u64, 1));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let first = cell.read(|v| *v);
+                let second = cell.read(|v| *v);
+                assert!(first <= second && second <= 3);
+            })
+        };
+        for _ in 0..3 {
+            cell.apply(Add(1));
+        }
+        reader.join().unwrap();
+        assert_eq!(cell.read(|v| *v), 3);
+    });
+}
+
+#[test]
+fn sharded_writes_all_counted() {
+    model(|| {
+        let cell = Arc::new(ShardedCell::<u64>::with_shards(2, || 0));
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                cell.write(|n| n + 1);
+                cell.write(|n| n + 1);
+            })
+        };
+        cell.write(|n| n + 1);
+        assert!(cell.read_merged(|n| *n) <= 3);
+        writer.join().unwrap();
+        assert_eq!(cell.read_merged(|n| *n), 3);
+    });
+}
+
+#[test]
+fn core_cell_grace_period() {
+    model(|| {
+        let cell = Arc::new(CoreCell::new(Box::new(0u32)));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.read(|v| assert!(**v <= 2)))
+        };
+        cell.store(Box::new(1));
+        cell.write_discard(|v| Box::new(**v + 1));
+        reader.join().unwrap();
+        assert_eq!(cell.read(|v| **v), 2);
+    });
+}
+
+#[test]
+fn new_const_first_writes_race() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<_, Inline>::new_const(0u32));
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.write_discard(|n| n + 1))
+        };
+        cell.write_discard(|n| n + 1);
+        writer.join().unwrap();
+        assert_eq!(cell.read(|n| *n), 2);
+    });
+}
+
+#[test]
+fn new_const_read_against_first_write() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<_, Inline>::new_const(Box::new(1u32)));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let first = cell.read(|n| **n);
+                let second = cell.read(|n| **n);
+                assert!(first <= second);
+            })
+        };
+        cell.store(Box::new(2));
+        reader.join().unwrap();
+    });
+}
+
+#[test]
+fn inline_spin_cell_read_against_first_store() {
+    model(|| {
+        let cell = Arc::new(SpinCell::new_const(Box::new(1u32)));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let first = cell.read(|n| **n);
+                let second = cell.read(|n| **n);
+                assert!(first <= second);
+            })
+        };
+        cell.store(Box::new(2));
+        reader.join().unwrap();
+        assert_eq!(cell.read(|n| **n), 2);
+    });
+}
+
+#[test]
+fn lazy_cell_first_readers_race() {
+    model(|| {
+        let next = loom::sync::atomic::AtomicU32::new(0);
+        let cell = Arc::new(LazyCell::new(move || {
+            Box::new(next.fetch_add(1, loom::sync::atomic::Ordering::Relaxed))
+        }));
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.read(|n| **n))
+        };
+        let mine = cell.read(|n| **n);
+        assert_eq!(reader.join().unwrap(), mine);
+        cell.store(Box::new(7));
+        assert_eq!(cell.read(|n| **n), 7);
+    });
+}
+
+#[test]
+fn get_or_init_first_readers_race() {
+    model(|| {
+        let cell = Arc::new(LockFreeCell::<_, Inline>::uninit());
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.get_or_init(|| Box::new(1u32), |n| **n))
+        };
+        let mine = cell.get_or_init(|| Box::new(2u32), |n| **n);
+        assert_eq!(reader.join().unwrap(), mine);
+        assert_eq!(cell.read(|n| **n), mine);
+    });
+}
@@ -0,0 +1,335 @@
+use crossbeam_utils::CachePadded;
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash},
+    ptr,
+};
+
+use crate::sync::{AtomicBool, AtomicPtr, AtomicUsize, Backoff, Collector, Ordering, protect};
+use crate::sz::{Node, NodeStrategy, TlsCache, reclaim};
+
+const INITIAL_BUCKETS: usize = 16;
+/// Grow once there are this many entries per bucket on average.
+const LOAD_FACTOR: usize = 2;
+/// Tags a bucket whose entries are being moved to a bigger table. Bucket nodes are at
+/// least pointer-aligned, so the low bit is free.
+const FROZEN: usize = 1;
+
+type Entries<K, V> = Vec<(K, V)>;
+type Bucket<K, V> = Node<Entries<K, V>, ()>;
+
+/// Buckets are copy-on-write nodes; null means empty.
+struct Table<K, V> {
+    buckets: Box<[AtomicPtr<Bucket<K, V>>]>,
+}
+
+impl<K, V> Table<K, V> {
+    fn new(buckets: impl IntoIterator<Item = *mut Bucket<K, V>>) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            buckets: buckets.into_iter().map(AtomicPtr::new).collect(),
+        }))
+    }
+}
+
+fn untagged<T>(ptr: *mut T) -> *mut T {
+    (ptr as usize & !FROZEN) as *mut T
+}
+
+fn alloc_bucket<K, V>(entries: Entries<K, V>) -> *mut Bucket<K, V> {
+    if entries.is_empty() {
+        ptr::null_mut()
+    } else {
+        <TlsCache as NodeStrategy<Entries<K, V>>>::alloc(&(), entries)
+    }
+}
+
+/// Frees a table and the buckets it still points to.
+unsafe fn reclaim_table<K, V>(table: *mut Table<K, V>, _collector: &Collector) {
+    let table = unsafe { Box::from_raw(table) };
+    for bucket in &table.buckets {
+        let bucket = untagged(bucket.load(Ordering::Relaxed));
+        if !bucket.is_null() {
+            unsafe { <TlsCache as NodeStrategy<Entries<K, V>>>::release(bucket) };
+        }
+    }
+}
+
+/// Hash map whose buckets are copy-on-write nodes, reclaimed like [`LockFreeCell`](crate::LockFreeCell)
+/// nodes. Lookups cost about as much as a cell read and never wait, not even while the
+/// map grows. Writers copy one bucket; a writer that runs into a growing table waits
+/// for it to be published.
+pub struct LockFreeMap<K, V, H = RandomState> {
+    collector: Collector,
+    table: CachePadded<AtomicPtr<Table<K, V>>>,
+    len: AtomicUsize,
+    resizing: AtomicBool,
+    hasher: H,
+}
+
+impl<K, V, H> Drop for LockFreeMap<K, V, H> {
+    fn drop(&mut self) {
+        unsafe { reclaim_table(self.table.load(Ordering::Acquire), &self.collector) };
+    }
+}
+
+unsafe impl<K: Send, V: Send, H: Send> Send for LockFreeMap<K, V, H> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, H: Sync> Sync for LockFreeMap<K, V, H> {}
+
+impl<K, V> LockFreeMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V> Default for LockFreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, H> LockFreeMap<K, V, H> {
+    pub fn with_hasher(hasher: H) -> Self {
+        Self {
+            collector: Collector::new(),
+            table: CachePadded::new(AtomicPtr::new(Table::new(
+                (0..INITIAL_BUCKETS).map(|_| ptr::null_mut()),
+            ))),
+            len: AtomicUsize::new(0),
+            resizing: AtomicBool::new(false),
+            hasher,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Hash + Eq, V, H: BuildHasher> LockFreeMap<K, V, H> {
+    fn index<Q: Hash + ?Sized>(&self, key: &Q, buckets: usize) -> usize {
+        self.hasher.hash_one(key) as usize & (buckets - 1)
+    }
+
+    /// Calls `f` with the value of `key`, if there is one.
+    pub fn get<Q, R>(&self, key: &Q, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let guard = self.collector.enter();
+        let table = unsafe { &*protect(&guard, &self.table, Ordering::Acquire) };
+        let slot = &table.buckets[self.index(key, table.buckets.len())];
+        let bucket = untagged(protect(&guard, slot, Ordering::Acquire));
+        if bucket.is_null() {
+            return None;
+        }
+        unsafe { Node::get(bucket) }
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| f(v))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key, |_| ()).is_some()
+    }
+
+    /// Replaces the bucket of `key` with `f(entries)`, or keeps it if `f` returns `None`.
+    /// Returns what `f` returned alongside the new entries.
+    fn modify<Q, R>(
+        &self,
+        key: &Q,
+        f: impl Fn(&[(K, V)]) -> Option<(Entries<K, V>, R)>,
+    ) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let guard = self.collector.enter();
+        let backoff = Backoff::new();
+        loop {
+            let table_ptr = protect(&guard, &self.table, Ordering::Acquire);
+            let table = unsafe { &*table_ptr };
+            let slot = &table.buckets[self.index(key, table.buckets.len())];
+            let bucket = protect(&guard, slot, Ordering::Acquire);
+            if bucket as usize & FROZEN != 0 {
+                // Being moved to a bigger table; retry there once it is published.
+                backoff.snooze();
+                continue;
+            }
+            let entries = if bucket.is_null() {
+                &[][..]
+            } else {
+                unsafe { Node::get(bucket) }.as_slice()
+            };
+            let (new_entries, ret) = f(entries)?;
+            let new = alloc_bucket(new_entries);
+            match slot.compare_exchange(bucket, new, Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => {
+                    if !bucket.is_null() {
+                        unsafe {
+                            self.collector
+                                .retire(bucket, reclaim::<Entries<K, V>, TlsCache>)
+                        };
+                    }
+                    return Some(ret);
+                }
+                Err(_) => {
+                    if !new.is_null() {
+                        unsafe { <TlsCache as NodeStrategy<Entries<K, V>>>::release(new) };
+                    }
+                    backoff.spin();
+                }
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, H: BuildHasher> LockFreeMap<K, V, H> {
+    /// Inserts `value` and returns a clone of the value it replaced.
+    /// Readers may still hold the old entry, so it cannot be moved out.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let old = self
+            .modify(&key, |entries| {
+                let mut new = entries.to_vec();
+                let old = match new.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, v)) => Some(std::mem::replace(v, value.clone())),
+                    None => {
+                        new.push((key.clone(), value.clone()));
+                        None
+                    }
+                };
+                Some((new, old))
+            })
+            .flatten();
+        if old.is_none() {
+            let len = self.len.fetch_add(1, Ordering::Relaxed) + 1;
+            let guard = self.collector.enter();
+            let table = protect(&guard, &self.table, Ordering::Acquire);
+            if len > unsafe { &*table }.buckets.len() * LOAD_FACTOR {
+                self.grow(table);
+            }
+        }
+        old
+    }
+
+    /// Removes `key` and returns a clone of its value.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let old = self.modify(key, |entries| {
+            let i = entries.iter().position(|(k, _)| k.borrow() == key)?;
+            let mut new = entries.to_vec();
+            let (_, v) = new.swap_remove(i);
+            Some((new, v))
+        });
+        if old.is_some() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+        }
+        old
+    }
+
+    /// Replaces the value of `key` with `f(value)`, retrying `f` if another writer got
+    /// to the bucket first. Returns `false` if there is no such key.
+    pub fn update<Q>(&self, key: &Q, f: impl Fn(&V) -> V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.modify(key, |entries| {
+            let i = entries.iter().position(|(k, _)| k.borrow() == key)?;
+            let mut new = entries.to_vec();
+            new[i].1 = f(&entries[i].1);
+            Some((new, ()))
+        })
+        .is_some()
+    }
+
+    /// Doubles the bucket count. Freezes every bucket of `old` so writers stop touching
+    /// it, copies the entries into a new table and publishes that. Readers keep using
+    /// the frozen buckets until they see the new table.
+    #[cold]
+    fn grow(&self, old: *mut Table<K, V>) {
+        if self
+            .resizing
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let buckets = &unsafe { &*old }.buckets;
+        let mut resize = Resize {
+            resizing: &self.resizing,
+            buckets,
+            frozen: 0,
+        };
+        if self.table.load(Ordering::Acquire) != old {
+            return;
+        }
+        let n = buckets.len() * 2;
+        let mut parts: Vec<Entries<K, V>> = (0..n).map(|_| Vec::new()).collect();
+        for slot in buckets {
+            let mut bucket = slot.load(Ordering::Acquire);
+            while let Err(current) = slot.compare_exchange_weak(
+                bucket,
+                (bucket as usize | FROZEN) as *mut _,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                bucket = current;
+            }
+            resize.frozen += 1;
+            if !bucket.is_null() {
+                for (k, v) in unsafe { Node::get(bucket) } {
+                    parts[self.index(k, n)].push((k.clone(), v.clone()));
+                }
+            }
+        }
+        let new = Table::new(parts.into_iter().map(alloc_bucket));
+        self.table.store(new, Ordering::Release);
+        resize.frozen = 0;
+        unsafe { self.collector.retire(old, reclaim_table::<K, V>) };
+    }
+}
+
+/// Ends a [`grow`](LockFreeMap::grow). If a clone or the hasher panicked before the new
+/// table was published, this thaws the buckets frozen so far, so writers stop waiting.
+struct Resize<'a, K, V> {
+    resizing: &'a AtomicBool,
+    buckets: &'a [AtomicPtr<Bucket<K, V>>],
+    frozen: usize,
+}
+
+impl<K, V> Drop for Resize<'_, K, V> {
+    fn drop(&mut self) {
+        // Frozen buckets only change here: writers wait, and `resizing` keeps out other grows.
+        for slot in &self.buckets[..self.frozen] {
+            slot.store(untagged(slot.load(Ordering::Relaxed)), Ordering::Release);
+        }
+        self.resizing.store(false, Ordering::Release);
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, H> fmt::Debug for LockFreeMap<K, V, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let guard = self.collector.enter();
+        let table = unsafe { &*protect(&guard, &self.table, Ordering::Acquire) };
+        let mut map = f.debug_map();
+        for slot in &table.buckets {
+            let bucket = untagged(protect(&guard, slot, Ordering::Acquire));
+            if !bucket.is_null() {
+                map.entries(unsafe { Node::get(bucket) }.iter().map(|(k, v)| (k, v)));
+            }
+        }
+        map.finish()
+    }
+}
@@ -0,0 +1,558 @@
+use std::{fmt, ops::Index, sync::Arc};
+
+use crate::sz::{LockFreeCell, NodeStrategy};
+
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+
+#[derive(Clone)]
+enum Node<T> {
+    /// `sizes` is `None` while every child but the last is full, so the child holding an
+    /// index follows from its bits. Otherwise the node is relaxed and `sizes[i]` counts the
+    /// values in `children[..=i]`.
+    Branch {
+        children: Vec<Arc<Node<T>>>,
+        sizes: Option<Vec<usize>>,
+    },
+    Leaf(Vec<T>),
+}
+
+/// A chain of single-child branches from `level` down to `leaf`.
+fn new_path<T>(level: u32, leaf: Vec<T>) -> Arc<Node<T>> {
+    if level == 0 {
+        Arc::new(Node::Leaf(leaf))
+    } else {
+        Arc::new(Node::Branch {
+            children: vec![new_path(level - BITS, leaf)],
+            sizes: None,
+        })
+    }
+}
+
+/// The child of a branch at `level` holding `index`, and the index within that child.
+fn locate(sizes: Option<&[usize]>, level: u32, index: usize) -> (usize, usize) {
+    match sizes {
+        None => (index >> level, index & ((1 << level) - 1)),
+        Some(sizes) => {
+            let slot = sizes.partition_point(|&n| n <= index);
+            (slot, index - slot.checked_sub(1).map_or(0, |i| sizes[i]))
+        }
+    }
+}
+
+impl<T> Node<T> {
+    fn empty() -> Arc<Self> {
+        Arc::new(Node::Branch {
+            children: Vec::new(),
+            sizes: None,
+        })
+    }
+
+    fn children(&self) -> &[Arc<Self>] {
+        match self {
+            Node::Branch { children, .. } => children,
+            Node::Leaf(_) => unreachable!("leaves are only at level 0"),
+        }
+    }
+
+    /// Values or children held directly.
+    fn width(&self) -> usize {
+        match self {
+            Node::Branch { children, .. } => children.len(),
+            Node::Leaf(values) => values.len(),
+        }
+    }
+
+    /// Number of values under this node, which is at `level`.
+    fn size(&self, level: u32) -> usize {
+        match self {
+            Node::Leaf(values) => values.len(),
+            Node::Branch {
+                sizes: Some(sizes), ..
+            } => sizes.last().copied().unwrap_or(0),
+            Node::Branch {
+                children,
+                sizes: None,
+            } => children.last().map_or(0, |last| {
+                ((children.len() - 1) << level) + last.size(level - BITS)
+            }),
+        }
+    }
+
+    /// Running totals for the children of a relaxed branch at `level`.
+    fn sizes(children: &[Arc<Self>], level: u32) -> Vec<usize> {
+        let mut total = 0;
+        children
+            .iter()
+            .map(|child| {
+                total += child.size(level - BITS);
+                total
+            })
+            .collect()
+    }
+
+    /// A branch at `level`, relaxed unless every child but the last is full.
+    fn branch(children: Vec<Arc<Self>>, level: u32) -> Arc<Self> {
+        let dense = children
+            .iter()
+            .rev()
+            .skip(1)
+            .all(|child| child.size(level - BITS) == 1 << level);
+        let sizes = (!dense).then(|| Self::sizes(&children, level));
+        Arc::new(Node::Branch { children, sizes })
+    }
+
+    /// The leaf holding `index` under this node at `level`, and the index within it.
+    fn leaf(&self, mut level: u32, mut index: usize) -> (&[T], usize) {
+        let mut node = self;
+        loop {
+            match node {
+                Node::Branch { children, sizes } => {
+                    let (slot, rest) = locate(sizes.as_deref(), level, index);
+                    node = &children[slot];
+                    index = rest;
+                }
+                Node::Leaf(values) => return (values, index),
+            }
+            level -= BITS;
+        }
+    }
+}
+
+impl<T: Clone> Node<T> {
+    fn set(node: &mut Arc<Self>, level: u32, index: usize, value: T) {
+        match Arc::make_mut(node) {
+            Node::Branch { children, sizes } => {
+                let (slot, rest) = locate(sizes.as_deref(), level, index);
+                Self::set(&mut children[slot], level - BITS, rest, value)
+            }
+            Node::Leaf(values) => values[index] = value,
+        }
+    }
+
+    /// Appends `leaf` after the values under `node`, a branch at `level`. Hands the leaf
+    /// back if the right edge is full.
+    fn push_leaf(node: &mut Arc<Self>, level: u32, leaf: Vec<T>) -> Result<(), Vec<T>> {
+        let Node::Branch { children, sizes } = Arc::make_mut(node) else {
+            unreachable!("leaves are only at level 0")
+        };
+        let len = leaf.len();
+        let leaf = match children.last_mut() {
+            Some(last) if level > BITS => match Self::push_leaf(last, level - BITS, leaf) {
+                Ok(()) => {
+                    if let Some(sizes) = sizes {
+                        *sizes.last_mut().unwrap() += len;
+                    }
+                    return Ok(());
+                }
+                Err(leaf) => leaf,
+            },
+            _ => leaf,
+        };
+        if children.len() == WIDTH {
+            return Err(leaf);
+        }
+        if sizes.is_none()
+            && children
+                .last()
+                .is_some_and(|last| last.size(level - BITS) != 1 << level)
+        {
+            // A sibling after a partly filled child: radix lookups no longer work.
+            *sizes = Some(Self::sizes(children, level));
+        }
+        children.push(new_path(level - BITS, leaf));
+        if let Some(sizes) = sizes {
+            sizes.push(sizes.last().copied().unwrap_or(0) + len);
+        }
+        Ok(())
+    }
+
+    /// Keeps the first `size` values under `node`, a branch at `level`; `size` is non-zero
+    /// and ends a leaf.
+    fn trim(node: &mut Arc<Self>, level: u32, size: usize) {
+        let Node::Branch { children, sizes } = Arc::make_mut(node) else {
+            unreachable!("leaves are only at level 0")
+        };
+        let (slot, rest) = locate(sizes.as_deref(), level, size - 1);
+        children.truncate(slot + 1);
+        if let Some(sizes) = sizes {
+            sizes.truncate(slot + 1);
+            sizes[slot] = size;
+        }
+        if level > BITS {
+            Self::trim(&mut children[slot], level - BITS, rest + 1);
+        }
+    }
+
+    /// `a` followed by `b`, both at `level`, in one node.
+    fn join(a: &Self, b: &Self, level: u32) -> Arc<Self> {
+        match (a, b) {
+            (Node::Leaf(a), Node::Leaf(b)) => Arc::new(Node::Leaf([&a[..], &b[..]].concat())),
+            _ => Self::branch([a.children(), b.children()].concat(), level),
+        }
+    }
+
+    /// Joins `left` and `right`, both branches at `level`, into one or two branches at
+    /// `level`. Only the nodes along the seam are rebuilt, and neighbours there that fit
+    /// in one node are merged, so every two adjacent nodes hold more than `WIDTH` children
+    /// or values between them and the tree stays O(log n) deep.
+    fn concat(left: &Self, right: &Self, level: u32) -> Vec<Arc<Self>> {
+        let (left, right) = (left.children(), right.children());
+        let (last, first) = (&left[left.len() - 1], &right[0]);
+        let middle = if level == BITS {
+            vec![last.clone(), first.clone()]
+        } else {
+            Self::concat(last, first, level - BITS)
+        };
+        let mut all = left[..left.len() - 1].to_vec();
+        // The seam runs from the last untouched left child to the first untouched right one.
+        let mut i = all.len().saturating_sub(1);
+        all.extend(middle);
+        let mut end = all.len() + usize::from(right.len() > 1);
+        all.extend_from_slice(&right[1..]);
+        while i + 1 < end {
+            if all[i].width() + all[i + 1].width() <= WIDTH {
+                all[i] = Self::join(&all[i], &all[i + 1], level - BITS);
+                all.remove(i + 1);
+                end -= 1;
+            } else {
+                i += 1;
+            }
+        }
+        if all.len() <= WIDTH {
+            vec![Self::branch(all, level)]
+        } else {
+            let rest = all.split_off(WIDTH);
+            vec![Self::branch(all, level), Self::branch(rest, level)]
+        }
+    }
+}
+
+/// Persistent vector: a relaxed radix balanced (RRB) tree of 32-wide nodes plus a tail
+/// buffer. Cloning is O(1); `push`, `set`, `truncate` and `append` copy only the nodes
+/// they touch and share the rest with the versions they came from.
+///
+/// Pushes keep the tree dense, where indexing is pure radix arithmetic. `append` joins two
+/// trees in O(log n) and leaves relaxed nodes along the seam, which carry a table of
+/// child sizes to search instead.
+pub struct PersistentVec<T> {
+    root: Arc<Node<T>>,
+    /// The last 1..=32 values (empty only when the vector is), kept out of the tree so
+    /// most pushes copy one short buffer.
+    tail: Arc<Vec<T>>,
+    len: usize,
+    /// Level of `root`; leaves are level 0.
+    shift: u32,
+}
+
+/// A [`LockFreeCell`] holding a [`PersistentVec`]: writers publish a new version by
+/// swapping the head, readers index into the version they loaded via [`read`](LockFreeCell::read).
+pub type LockFreeVecCell<T> = LockFreeCell<PersistentVec<T>>;
+
+impl<T> Clone for PersistentVec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+            len: self.len,
+            shift: self.shift,
+        }
+    }
+}
+
+impl<T> Default for PersistentVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PersistentVec<T> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::empty(),
+            tail: Arc::new(Vec::new()),
+            len: 0,
+            shift: BITS,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn tail_offset(&self) -> usize {
+        self.len - self.tail.len()
+    }
+
+    /// The leaf holding `index`, which must be in the tree, and the index within it.
+    fn leaf(&self, index: usize) -> (&[T], usize) {
+        self.root.leaf(self.shift, index)
+    }
+
+    /// Level of the root.
+    #[cfg(all(test, not(loom)))]
+    pub(crate) fn shift(&self) -> u32 {
+        self.shift
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            None
+        } else if index >= self.tail_offset() {
+            Some(&self.tail[index - self.tail_offset()])
+        } else {
+            let (leaf, i) = self.leaf(index);
+            Some(&leaf[i])
+        }
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+    pub fn last(&self) -> Option<&T> {
+        self.tail.last()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            vec: self,
+            next: 0,
+            chunk: [].iter(),
+        }
+    }
+}
+
+impl<T: Clone> PersistentVec<T> {
+    pub fn push(&mut self, value: T) {
+        if self.tail.len() == WIDTH {
+            let leaf = Arc::unwrap_or_clone(std::mem::replace(
+                &mut self.tail,
+                Arc::new(Vec::with_capacity(WIDTH)),
+            ));
+            self.push_tree_leaf(leaf);
+        }
+        Arc::make_mut(&mut self.tail).push(value);
+        self.len += 1;
+    }
+
+    /// Moves `leaf` from the tail into the tree.
+    fn push_tree_leaf(&mut self, leaf: Vec<T>) {
+        if let Err(leaf) = Node::push_leaf(&mut self.root, self.shift, leaf) {
+            // The right edge is full: grow a new root above it.
+            let sibling = new_path(self.shift, leaf);
+            let old = std::mem::replace(&mut self.root, Node::empty());
+            self.shift += BITS;
+            self.root = Node::branch(vec![old, sibling], self.shift);
+        }
+    }
+
+    /// Drops roots with a single child.
+    fn collapse_root(&mut self) {
+        while let Node::Branch { children, .. } = &*self.root
+            && self.shift > BITS
+            && children.len() == 1
+        {
+            self.root = children[0].clone();
+            self.shift -= BITS;
+        }
+    }
+
+    /// Appends the values of `other`. Its tree is joined on in O(log n), sharing all but
+    /// the nodes along the seam.
+    pub fn append(&mut self, other: &Self) {
+        if other.tail_offset() == 0 {
+            // Only a tail: pushing keeps the tree dense.
+            other.tail.iter().for_each(|v| self.push(v.clone()));
+            return;
+        }
+        if self.is_empty() {
+            *self = other.clone();
+            return;
+        }
+        let tail = Arc::unwrap_or_clone(std::mem::take(&mut self.tail));
+        self.push_tree_leaf(tail);
+        let shift = self.shift.max(other.shift);
+        let lift = |mut node: Arc<Node<T>>, from: u32| {
+            for _ in (from..shift).step_by(BITS as usize) {
+                node = Arc::new(Node::Branch {
+                    children: vec![node],
+                    sizes: None,
+                });
+            }
+            node
+        };
+        let left = lift(std::mem::replace(&mut self.root, Node::empty()), self.shift);
+        let right = lift(other.root.clone(), other.shift);
+        let mut joined = Node::concat(&left, &right, shift);
+        self.shift = shift;
+        self.root = if joined.len() == 1 {
+            joined.pop().unwrap()
+        } else {
+            self.shift += BITS;
+            Node::branch(joined, self.shift)
+        };
+        self.collapse_root();
+        self.tail = other.tail.clone();
+        self.len += other.len;
+    }
+
+    /// # Panics
+    /// If `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) {
+        assert!(
+            index < self.len,
+            "index {index} out of bounds (len {})",
+            self.len
+        );
+        let tail_offset = self.tail_offset();
+        if index >= tail_offset {
+            Arc::make_mut(&mut self.tail)[index - tail_offset] = value;
+        } else {
+            Node::set(&mut self.root, self.shift, index, value);
+        }
+    }
+
+    /// Shortens the vector to `len` values; does nothing if it is not longer.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let tail_offset = self.tail_offset();
+        if len > tail_offset {
+            Arc::make_mut(&mut self.tail).truncate(len - tail_offset);
+        } else if len == 0 {
+            *self = Self::new();
+            return;
+        } else {
+            // The leaf holding the new last value becomes the tail.
+            let (leaf, i) = self.leaf(len - 1);
+            let new_offset = len - 1 - i;
+            self.tail = Arc::new(leaf[..=i].to_vec());
+            if new_offset == 0 {
+                self.root = Node::empty();
+                self.shift = BITS;
+            } else {
+                Node::trim(&mut self.root, self.shift, new_offset);
+                self.collapse_root();
+            }
+        }
+        self.len = len;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let last = self.last()?.clone();
+        self.truncate(self.len - 1);
+        Some(last)
+    }
+}
+
+impl<T> Index<usize> for PersistentVec<T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &T {
+        self.get(index)
+            .unwrap_or_else(|| panic!("index {index} out of bounds (len {})", self.len))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PersistentVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for PersistentVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone> FromIterator<T> for PersistentVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Self::new();
+        iter.into_iter().for_each(|v| vec.push(v));
+        vec
+    }
+}
+
+impl<'a, T> IntoIterator for &'a PersistentVec<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Walks a [`PersistentVec`] one leaf at a time.
+pub struct Iter<'a, T> {
+    vec: &'a PersistentVec<T>,
+    next: usize,
+    chunk: std::slice::Iter<'a, T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        if let Some(v) = self.chunk.next() {
+            return Some(v);
+        }
+        if self.next >= self.vec.len {
+            return None;
+        }
+        let chunk = if self.next >= self.vec.tail_offset() {
+            &self.vec.tail[..]
+        } else {
+            let (leaf, i) = self.vec.leaf(self.next);
+            &leaf[i..]
+        };
+        self.next += chunk.len();
+        self.chunk = chunk.iter();
+        self.chunk.next()
+    }
+}
+
+/// Vector operations on a cell holding a [`PersistentVec`]. Each write clones the
+/// root, which is O(1), and path-copies O(log n) nodes.
+impl<T: Clone, S: NodeStrategy<PersistentVec<T>>> LockFreeCell<PersistentVec<T>, S> {
+    pub fn push(&self, value: T) {
+        self.write_discard(|vec| {
+            let mut vec = vec.clone();
+            vec.push(value.clone());
+            vec
+        });
+    }
+
+    /// # Panics
+    /// If `index` is out of bounds; the cell keeps its value.
+    pub fn set(&self, index: usize, value: T) {
+        self.write_discard(|vec| {
+            let mut vec = vec.clone();
+            vec.set(index, value.clone());
+            vec
+        });
+    }
+
+    pub fn append(&self, other: &PersistentVec<T>) {
+        self.write_discard(|vec| {
+            let mut vec = vec.clone();
+            vec.append(other);
+            vec
+        });
+    }
+
+    pub fn truncate(&self, len: usize) {
+        self.write_discard(|vec| {
+            let mut vec = vec.clone();
+            vec.truncate(len);
+            vec
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.read(PersistentVec::len)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,149 @@
+use crossbeam_utils::CachePadded;
+use std::{fmt, mem::MaybeUninit, ptr};
+
+use crate::sync::{AtomicPtr, Backoff, Collector, Ordering, protect};
+use crate::sz::{Node, NodeStrategy, TlsCache, reclaim};
+
+struct Link<T> {
+    next: AtomicPtr<Node<Link<T>, ()>>,
+    /// Uninit in the sentinel; moved out by the `pop` that makes its node the sentinel.
+    value: MaybeUninit<T>,
+}
+
+fn alloc<T>(value: MaybeUninit<T>) -> *mut Node<Link<T>, ()> {
+    TlsCache::alloc(
+        &(),
+        Link {
+            next: AtomicPtr::new(ptr::null_mut()),
+            value,
+        },
+    )
+}
+
+/// Michael–Scott queue on the same nodes and collector as
+/// [`LockFreeCell`](crate::LockFreeCell). `head` is a sentinel whose successor holds
+/// the front value; `tail` may lag one node behind, and whoever sees that helps it on.
+pub struct LockFreeQueue<T> {
+    collector: Collector,
+    head: CachePadded<AtomicPtr<Node<Link<T>, ()>>>,
+    tail: CachePadded<AtomicPtr<Node<Link<T>, ()>>>,
+}
+
+impl<T> Drop for LockFreeQueue<T> {
+    fn drop(&mut self) {
+        let sentinel = self.head.load(Ordering::Acquire);
+        let mut node = unsafe { Node::get(sentinel) }.next.load(Ordering::Relaxed);
+        unsafe { <TlsCache as NodeStrategy<Link<T>>>::release(sentinel) };
+        while !node.is_null() {
+            unsafe {
+                let link = Node::get(node);
+                let next = link.next.load(Ordering::Relaxed);
+                drop(link.value.assume_init_read());
+                <TlsCache as NodeStrategy<Link<T>>>::release(node);
+                node = next;
+            }
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for LockFreeQueue<T> {}
+unsafe impl<T: Send> Sync for LockFreeQueue<T> {}
+
+impl<T> Default for LockFreeQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> LockFreeQueue<T> {
+    pub fn new() -> Self {
+        let sentinel = alloc(MaybeUninit::uninit());
+        let batch_size = <TlsCache as NodeStrategy<Link<T>>>::BATCH_SIZE;
+        Self {
+            collector: Collector::new().batch_size(batch_size),
+            head: CachePadded::new(AtomicPtr::new(sentinel)),
+            tail: CachePadded::new(AtomicPtr::new(sentinel)),
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = alloc(MaybeUninit::new(value));
+        let guard = self.collector.enter();
+        let backoff = Backoff::new();
+        loop {
+            let tail = protect(&guard, &self.tail, Ordering::Acquire);
+            let link = unsafe { Node::get(tail) };
+            match link.next.compare_exchange(
+                ptr::null_mut(),
+                node,
+                Ordering::Release,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    // Fails only if someone already helped.
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        node,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                    return;
+                }
+                Err(next) => {
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        next,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                }
+            }
+            backoff.spin();
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.collector.enter();
+        let backoff = Backoff::new();
+        loop {
+            let head = protect(&guard, &self.head, Ordering::Acquire);
+            let next = protect(&guard, &unsafe { Node::get(head) }.next, Ordering::Acquire);
+            if next.is_null() {
+                return None;
+            }
+            let tail = self.tail.load(Ordering::Acquire);
+            if tail == head {
+                // The pusher of `next` hasn't moved `tail` yet; `head` must not pass it.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                let value = unsafe { Node::get(next).value.assume_init_read() };
+                unsafe { self.collector.retire(head, reclaim::<Link<T>, TlsCache>) };
+                return Some(value);
+            }
+            backoff.spin();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let guard = self.collector.enter();
+        let head = protect(&guard, &self.head, Ordering::Acquire);
+        unsafe { Node::get(head) }
+            .next
+            .load(Ordering::Acquire)
+            .is_null()
+    }
+}
+
+impl<T> fmt::Debug for LockFreeQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockFreeQueue").finish_non_exhaustive()
+    }
+}
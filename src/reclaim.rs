@@ -0,0 +1,122 @@
+//! Reclamation backends for [`CoreCell`](crate::CoreCell).
+
+use alloc::boxed::Box;
+use crossbeam_utils::CachePadded;
+
+use crate::sync::{AtomicPtr, AtomicUsize, Backoff, Ordering, fence};
+
+/// Decides when a replaced [`CoreCell`](crate::CoreCell) value can be freed.
+///
+/// # Safety
+/// A pointer passed to `retire` must stay valid for every guard entered before it was
+/// unlinked, and for pointers such a guard's `protect` returned.
+pub unsafe trait Reclaim {
+    type Guard<'a>
+    where
+        Self: 'a;
+
+    fn enter(&self) -> Self::Guard<'_>;
+    /// Loads `ptr`, which stays valid for as long as `guard` lives.
+    fn protect<T>(guard: &Self::Guard<'_>, ptr: &AtomicPtr<T>) -> *mut T;
+    /// Frees the `Box` at `ptr` once no guard can still reach it.
+    ///
+    /// # Safety
+    /// `ptr` must come from `Box::into_raw` and be unlinked, so new guards cannot find it.
+    unsafe fn retire<T>(&self, ptr: *mut T);
+}
+
+unsafe fn free_box<T>(ptr: *mut T) {
+    drop(unsafe { Box::from_raw(ptr) });
+}
+
+/// Lets writers wait out the readers instead of deferring frees, so it needs nothing but
+/// atomics. Readers count themselves in one of two counters; `retire` flips new readers
+/// over to the other one and waits for each counter to drain in turn, then frees at once.
+///
+/// Reads stay wait-free, but a write blocks until the readers it overlaps have left, so
+/// writing to a cell from inside one of its reads never returns.
+pub struct GracePeriod {
+    epoch: AtomicUsize,
+    readers: [CachePadded<AtomicUsize>; 2],
+}
+
+impl GracePeriod {
+    pub fn new() -> Self {
+        Self {
+            epoch: AtomicUsize::new(0),
+            readers: [
+                CachePadded::new(AtomicUsize::new(0)),
+                CachePadded::new(AtomicUsize::new(0)),
+            ],
+        }
+    }
+
+    /// Returns once every guard entered before the call has been dropped.
+    pub fn synchronize(&self) {
+        // Pairs with the fence in `enter`: a reader this misses loads the pointer after the
+        // caller unlinked it.
+        fence(Ordering::SeqCst);
+        for _ in 0..2 {
+            let old = self.epoch.fetch_add(1, Ordering::SeqCst) & 1;
+            let backoff = Backoff::new();
+            while self.readers[old].load(Ordering::Acquire) != 0 {
+                backoff.snooze();
+            }
+        }
+    }
+}
+
+impl Default for GracePeriod {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct GraceGuard<'a>(&'a AtomicUsize);
+
+impl Drop for GraceGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+}
+
+unsafe impl Reclaim for GracePeriod {
+    type Guard<'a> = GraceGuard<'a>;
+
+    fn enter(&self) -> GraceGuard<'_> {
+        let readers = &self.readers[self.epoch.load(Ordering::Relaxed) & 1];
+        readers.fetch_add(1, Ordering::Relaxed);
+        // Either `synchronize` sees this reader or the reader's loads see the unlink.
+        fence(Ordering::SeqCst);
+        GraceGuard(readers)
+    }
+
+    fn protect<T>(_guard: &GraceGuard<'_>, ptr: &AtomicPtr<T>) -> *mut T {
+        ptr.load(Ordering::Acquire)
+    }
+
+    unsafe fn retire<T>(&self, ptr: *mut T) {
+        self.synchronize();
+        unsafe { free_box(ptr) };
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl Reclaim for crate::sync::Collector {
+    type Guard<'a> = crate::sync::Guard<'a>;
+
+    fn enter(&self) -> Self::Guard<'_> {
+        self.enter()
+    }
+
+    fn protect<T>(guard: &Self::Guard<'_>, ptr: &AtomicPtr<T>) -> *mut T {
+        crate::sync::protect(guard, ptr, Ordering::Acquire)
+    }
+
+    unsafe fn retire<T>(&self, ptr: *mut T) {
+        unsafe fn reclaim<T>(ptr: *mut T, _collector: &crate::sync::Collector) {
+            unsafe { free_box(ptr) };
+        }
+        unsafe { self.retire(ptr, reclaim::<T>) };
+    }
+}
@@ -0,0 +1,145 @@
+use crossbeam_utils::CachePadded;
+use std::{fmt, marker::PhantomData, ops::AddAssign, ptr};
+
+use crate::sync::{AtomicPtr, AtomicUsize, Collector, Ordering, protect, thread_local};
+use crate::sz::{BATCH_SIZE, Node, NodeStrategy, TlsCache, Unpublished, reclaim};
+
+#[cfg(not(loom))]
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+// Reset for every execution, so a thread picks the same shard each time loom replays it.
+#[cfg(loom)]
+loom::lazy_static! {
+    static ref NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+}
+
+thread_local! {
+    /// Handed out round-robin as threads first write, so they spread evenly.
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+fn shard_index(mask: usize) -> usize {
+    SHARD.with(|shard| *shard) & mask
+}
+
+type Shard<T> = CachePadded<AtomicPtr<Node<T, ()>>>;
+
+/// How [`ShardedCell::read_merged`] folds one shard into the total.
+pub trait Merge<T> {
+    fn merge(total: &mut T, shard: &T);
+}
+
+/// Adds the shards up, for counters.
+pub struct Sum;
+impl<T: for<'a> AddAssign<&'a T>> Merge<T> for Sum {
+    fn merge(total: &mut T, shard: &T) {
+        *total += shard;
+    }
+}
+
+/// A set of copy-on-write cells, each on its own cache line, that writers spread over by
+/// thread. Reads fold every shard with `M`, so this pays off for counters and metrics
+/// written much more often than they are read.
+pub struct ShardedCell<T, M = Sum> {
+    collector: Collector,
+    shards: Box<[Shard<T>]>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> Drop for ShardedCell<T, M> {
+    fn drop(&mut self) {
+        for shard in &self.shards {
+            unsafe { <TlsCache as NodeStrategy<T>>::release(shard.load(Ordering::Acquire)) };
+        }
+    }
+}
+
+unsafe impl<T: Send, M> Send for ShardedCell<T, M> {}
+unsafe impl<T: Send + Sync, M> Sync for ShardedCell<T, M> {}
+
+impl<T: Default, M: Merge<T>> ShardedCell<T, M> {
+    /// One shard per available CPU, each starting at `T::default()`.
+    pub fn new() -> Self {
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_shards(cpus, T::default)
+    }
+}
+
+impl<T: Default, M: Merge<T>> Default for ShardedCell<T, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, M: Merge<T>> ShardedCell<T, M> {
+    /// `shards` is rounded up to a power of two; `init` gives each shard its starting value,
+    /// usually the identity of `M`.
+    pub fn with_shards(shards: usize, mut init: impl FnMut() -> T) -> Self {
+        let shards = (0..shards.max(1).next_power_of_two())
+            .map(|_| CachePadded::new(AtomicPtr::new(TlsCache::alloc(&(), init()))))
+            .collect();
+        Self {
+            collector: Collector::new().batch_size(BATCH_SIZE),
+            shards,
+            merge: PhantomData,
+        }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Replaces this thread's shard with `f` of it, like
+    /// [`LockFreeCell::write_discard`](crate::LockFreeCell::write_discard).
+    pub fn write(&self, f: impl Fn(&T) -> T) {
+        let shard = &self.shards[shard_index(self.shards.len() - 1)];
+        let mut new_node = Unpublished::<T, TlsCache>(ptr::null_mut());
+        let guard = self.collector.enter();
+        loop {
+            let head = protect(&guard, shard, Ordering::Acquire);
+            let value = f(unsafe { Node::get(head) });
+            if new_node.0.is_null() {
+                new_node.0 = TlsCache::alloc(&(), value);
+            } else {
+                unsafe {
+                    Node::drop_value(new_node.0);
+                    Node::set(new_node.0, value);
+                }
+            }
+            if shard
+                .compare_exchange(head, new_node.0, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                new_node.0 = ptr::null_mut();
+                unsafe { self.collector.retire(head, reclaim::<T, TlsCache>) };
+                return;
+            }
+        }
+    }
+
+    /// Calls `f` with every shard merged into a copy of the first, all loaded under one
+    /// guard. Writes racing with the read may or may not be counted.
+    pub fn read_merged<R>(&self, f: impl FnOnce(&T) -> R) -> R
+    where
+        T: Clone,
+    {
+        let guard = self.collector.enter();
+        let mut shards = self
+            .shards
+            .iter()
+            .map(|shard| unsafe { Node::get(protect(&guard, shard, Ordering::Acquire)) });
+        let mut total = shards.next().unwrap().clone();
+        shards.for_each(|shard| M::merge(&mut total, shard));
+        f(&total)
+    }
+}
+
+impl<T: Clone + fmt::Debug, M: Merge<T>> fmt::Debug for ShardedCell<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read_merged(|v| {
+            f.debug_struct("ShardedCell")
+                .field("value", v)
+                .field("shards", &self.shards.len())
+                .finish()
+        })
+    }
+}
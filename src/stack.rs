@@ -0,0 +1,111 @@
+use crossbeam_utils::CachePadded;
+use std::{fmt, mem::ManuallyDrop, ptr};
+
+use crate::sync::{AtomicPtr, Backoff, Collector, Ordering, protect};
+use crate::sz::{Node, NodeStrategy, TlsCache, reclaim};
+
+struct Link<T> {
+    next: AtomicPtr<Node<Link<T>, ()>>,
+    /// Moved out by the `pop` that unlinks the node, so releasing the node leaves it alone.
+    value: ManuallyDrop<T>,
+}
+
+/// Treiber stack on the same nodes as [`LockFreeCell`](crate::LockFreeCell): allocations
+/// go through the [`TlsCache`] and popped nodes are retired to the stack's collector,
+/// which also rules out ABA on the head.
+pub struct LockFreeStack<T> {
+    collector: Collector,
+    head: CachePadded<AtomicPtr<Node<Link<T>, ()>>>,
+}
+
+impl<T> Drop for LockFreeStack<T> {
+    fn drop(&mut self) {
+        let mut node = self.head.load(Ordering::Acquire);
+        while !node.is_null() {
+            unsafe {
+                let link = Node::get(node);
+                let next = link.next.load(Ordering::Relaxed);
+                drop(ManuallyDrop::into_inner(ptr::read(&link.value)));
+                <TlsCache as NodeStrategy<Link<T>>>::release(node);
+                node = next;
+            }
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for LockFreeStack<T> {}
+// Values are only moved in and out, never shared by reference, so `T: Sync` isn't needed.
+unsafe impl<T: Send> Sync for LockFreeStack<T> {}
+
+impl<T> Default for LockFreeStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> LockFreeStack<T> {
+    pub fn new() -> Self {
+        let batch_size = <TlsCache as NodeStrategy<Link<T>>>::BATCH_SIZE;
+        Self {
+            collector: Collector::new().batch_size(batch_size),
+            head: CachePadded::new(AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = TlsCache::alloc(
+            &(),
+            Link {
+                next: AtomicPtr::new(ptr::null_mut()),
+                value: ManuallyDrop::new(value),
+            },
+        );
+        let link = unsafe { Node::get(node) };
+        let backoff = Backoff::new();
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            link.next.store(head, Ordering::Relaxed);
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+            backoff.spin();
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.collector.enter();
+        let backoff = Backoff::new();
+        loop {
+            let head = protect(&guard, &self.head, Ordering::Acquire);
+            if head.is_null() {
+                return None;
+            }
+            let link = unsafe { Node::get(head) };
+            let next = link.next.load(Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                let value = unsafe { ManuallyDrop::into_inner(ptr::read(&link.value)) };
+                unsafe { self.collector.retire(head, reclaim::<Link<T>, TlsCache>) };
+                return Some(value);
+            }
+            backoff.spin();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+}
+
+impl<T> fmt::Debug for LockFreeStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockFreeStack").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,203 @@
+//! Atomics and cells used by the cells, swapped for `loom`'s models under `cfg(loom)`.
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering, fence,
+};
+#[cfg(not(loom))]
+pub(crate) use crossbeam_utils::Backoff;
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use std::thread_local;
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering, fence,
+};
+#[cfg(loom)]
+pub(crate) use loom::thread_local;
+
+/// `UnsafeCell` with loom's closure API, so loom can check every access for races.
+#[cfg(not(loom))]
+pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    #[inline(always)]
+    pub(crate) const fn new(value: T) -> Self {
+        Self(core::cell::UnsafeCell::new(value))
+    }
+    #[inline(always)]
+    pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+    #[inline(always)]
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+    #[inline(always)]
+    pub(crate) fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
+
+#[cfg(loom)]
+pub(crate) use loom::cell::UnsafeCell;
+
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use seize::{Collector, LocalGuard as Guard};
+
+/// Loads `ptr` and protects the result for as long as `guard` lives.
+#[cfg(all(feature = "std", not(loom)))]
+#[inline(always)]
+pub(crate) fn protect<T>(guard: &impl seize::Guard, ptr: &AtomicPtr<T>, order: Ordering) -> *mut T {
+    guard.protect(ptr, order)
+}
+
+/// Stand-in for seize, whose atomics and barriers loom cannot see: retired pointers wait
+/// until no guard is left, with every hand-off going through a loom mutex. `pub` (with
+/// its guard) like seize's, since the sealed `CellState` of `sz` names it.
+#[cfg(loom)]
+pub struct Collector {
+    state: loom::sync::Mutex<Reclaim>,
+}
+
+#[cfg(loom)]
+type Retired = (*mut (), unsafe fn(*mut (), &Collector));
+
+#[cfg(loom)]
+struct Reclaim {
+    guards: usize,
+    retired: Vec<Retired>,
+}
+
+#[cfg(loom)]
+pub struct Guard<'a>(&'a Collector);
+
+#[cfg(loom)]
+impl Collector {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: loom::sync::Mutex::new(Reclaim {
+                guards: 0,
+                retired: Vec::new(),
+            }),
+        }
+    }
+    pub(crate) fn batch_size(self, _batch_size: usize) -> Self {
+        self
+    }
+    pub(crate) fn enter(&self) -> Guard<'_> {
+        self.state.lock().unwrap().guards += 1;
+        Guard(self)
+    }
+    pub(crate) unsafe fn retire<T>(&self, ptr: *mut T, reclaim: unsafe fn(*mut T, &Collector)) {
+        // Safety: `fn(*mut T)` and `fn(*mut ())` are ABI compatible for sized `T`.
+        let reclaim: unsafe fn(*mut (), &Collector) = unsafe { std::mem::transmute(reclaim) };
+        let mut state = self.state.lock().unwrap();
+        if state.guards == 0 {
+            drop(state);
+            unsafe { reclaim(ptr.cast(), self) };
+        } else {
+            state.retired.push((ptr.cast(), reclaim));
+        }
+    }
+    fn reclaim(&self, retired: Vec<Retired>) {
+        for (ptr, reclaim) in retired {
+            unsafe { reclaim(ptr, self) };
+        }
+    }
+}
+
+#[cfg(loom)]
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.guards -= 1;
+        if state.guards == 0 {
+            // Taken under the lock: anything retired after it is unlocked may be in use
+            // by a guard entered in the meantime.
+            let retired = std::mem::take(&mut state.retired);
+            drop(state);
+            self.0.reclaim(retired);
+        }
+    }
+}
+
+#[cfg(loom)]
+impl Drop for Collector {
+    fn drop(&mut self) {
+        let retired = std::mem::take(&mut self.state.lock().unwrap().retired);
+        self.reclaim(retired);
+    }
+}
+
+#[cfg(loom)]
+pub(crate) fn protect<T>(_guard: &Guard<'_>, ptr: &AtomicPtr<T>, order: Ordering) -> *mut T {
+    ptr.load(order)
+}
+
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use atomic_wait::{wait, wake_all};
+
+/// Without `std` there is no futex to sleep on; waking up at once is a valid spurious wakeup.
+#[cfg(not(any(feature = "std", loom)))]
+pub(crate) fn wait(_futex: &AtomicU32, _expected: u32) {}
+
+#[cfg(not(any(feature = "std", loom)))]
+pub(crate) fn wake_all(_futex: &AtomicU32) {}
+
+/// Whether the current thread is unwinding; always `false` without `std`.
+#[cfg(any(feature = "std", loom))]
+pub(crate) use std::thread::panicking;
+
+#[cfg(not(any(feature = "std", loom)))]
+pub(crate) fn panicking() -> bool {
+    false
+}
+
+/// Loom cannot block on a futex; waking up at once is a valid spurious wakeup.
+#[cfg(loom)]
+pub(crate) fn wait(_futex: &AtomicU32, _expected: u32) {
+    loom::thread::yield_now();
+}
+
+#[cfg(loom)]
+pub(crate) fn wake_all(_futex: &AtomicU32) {}
+
+/// Spin loops have to yield for loom to schedule the thread they wait on.
+#[cfg(loom)]
+pub(crate) struct Backoff {
+    step: std::cell::Cell<u32>,
+}
+
+#[cfg(loom)]
+impl Backoff {
+    const YIELD_LIMIT: u32 = 2;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            step: std::cell::Cell::new(0),
+        }
+    }
+    pub(crate) fn spin(&self) {
+        self.snooze();
+    }
+    pub(crate) fn snooze(&self) {
+        self.step.set(self.step.get() + 1);
+        loom::thread::yield_now();
+    }
+    pub(crate) fn is_completed(&self) -> bool {
+        self.step.get() > Self::YIELD_LIMIT
+    }
+}
+
+/// Declares a `const fn`, except under loom, whose atomics cannot be built in const context.
+macro_rules! const_fn {
+    ($(#[$attr:meta])* $vis:vis fn $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])* $vis const fn $($rest)*
+        #[cfg(loom)]
+        $(#[$attr])* $vis fn $($rest)*
+    };
+}
+pub(crate) use const_fn;
@@ -1,139 +1,733 @@
 use crossbeam_utils::CachePadded;
-use seize::{Guard, reclaim};
 use std::{
-    alloc::Layout,
-    cell::Cell,
-    mem::MaybeUninit,
-    sync::atomic::{AtomicPtr, Ordering},
+    alloc::Layout, cell::Cell, fmt, mem::MaybeUninit, ops::Deref, panic::RefUnwindSafe, ptr,
 };
 
-use seize::Collector;
+use crate::combine::{Batch, Combiner};
+use crate::sync::{
+    AtomicPtr, AtomicUsize, Backoff, Collector, Ordering, UnsafeCell, const_fn, fence, protect,
+    thread_local,
+};
+use crate::tagged::Inline;
+use private::CellState;
 
 const CACHE_SIZE: usize = 2;
 type CacheEntry = (*mut u8, Layout);
 const EMPTY_ENTRY: CacheEntry = (std::ptr::null_mut(), Layout::new::<()>());
 
+#[cfg(not(loom))]
 thread_local! {
     static NODE_CACHE: Cell<[CacheEntry; CACHE_SIZE]> = const {
         Cell::new([EMPTY_ENTRY; CACHE_SIZE])
     };
 }
-const BATCH_SIZE: usize = 32;
+// loom's `thread_local!` has no `const` initializers.
+#[cfg(loom)]
+thread_local! {
+    static NODE_CACHE: Cell<[CacheEntry; CACHE_SIZE]> = Cell::new([EMPTY_ENTRY; CACHE_SIZE]);
+}
+pub(crate) const BATCH_SIZE: usize = 32;
 const RO: Ordering = Ordering::Acquire;
 const WO: Ordering = Ordering::Release;
-pub struct LockFreeCell<T> {
-    collector: Collector,
-    head: CachePadded<AtomicPtr<Node<T>>>,
+
+pub(crate) mod private {
+    use super::{Collector, Once};
+
+    pub trait Sealed {}
+
+    /// The collector of a [`LockFreeCell`](super::LockFreeCell), and whatever its strategy
+    /// keeps next to it.
+    pub trait CellState<T> {
+        /// A null head means the value is [`inline`](Self::inline).
+        const INLINE: bool = false;
+
+        fn ready(collector: Collector) -> Self;
+        /// The collector, made by the first caller that needs one. Writers call this before
+        /// they publish a node.
+        fn collector(&self) -> &Collector;
+        /// # Safety
+        /// A node must have been published.
+        #[inline(always)]
+        unsafe fn published(&self) -> &Collector {
+            self.collector()
+        }
+        fn inline(&self) -> Option<&T> {
+            None
+        }
+    }
+
+    impl<T> CellState<T> for Collector {
+        fn ready(collector: Collector) -> Self {
+            collector
+        }
+        #[inline(always)]
+        fn collector(&self) -> &Collector {
+            self
+        }
+    }
+
+    /// State of a [`LockFreeCell<T, Inline>`](super::LockFreeCell): the value it was built
+    /// with, and a collector made by the first write. Without a value the cell is empty
+    /// until [`get_or_init`](super::LockFreeCell::get_or_init).
+    pub struct InlineState<T> {
+        pub(super) collector: Once<Collector>,
+        pub(super) value: Option<T>,
+    }
+
+    impl<T> CellState<T> for InlineState<T> {
+        const INLINE: bool = true;
+
+        fn ready(collector: Collector) -> Self {
+            Self {
+                collector: Once::ready(collector),
+                value: None,
+            }
+        }
+        fn collector(&self) -> &Collector {
+            self.collector.get_or_init(|| {
+                Collector::new().batch_size(<super::TlsCache as super::NodeStrategy<T>>::BATCH_SIZE)
+            })
+        }
+        #[inline(always)]
+        unsafe fn published(&self) -> &Collector {
+            // Made before the node was published, which the caller saw.
+            unsafe { self.collector.get_unchecked() }
+        }
+        #[inline(always)]
+        fn inline(&self) -> Option<&T> {
+            self.value.as_ref()
+        }
+    }
 }
-impl<T> Drop for LockFreeCell<T> {
+
+const UNINIT: usize = 0;
+const BUSY: usize = 1;
+const READY: usize = 2;
+
+/// A value set at most once; racing initializers wait for the first one.
+struct Once<T> {
+    state: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Once<T> {
+    const_fn! {
+        fn new() -> Self {
+            Self {
+                state: AtomicUsize::new(UNINIT),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+    }
+
+    fn ready(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(READY),
+            value: UnsafeCell::new(MaybeUninit::new(value)),
+        }
+    }
+
+    #[inline]
+    fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == READY {
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// # Safety
+    /// The value must be set, and that must happen before this call.
+    #[inline(always)]
+    unsafe fn get_unchecked(&self) -> &T {
+        unsafe { self.value.with(|v| (*v).assume_init_ref()) }
+    }
+
+    fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        if self
+            .state
+            .compare_exchange(UNINIT, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            unsafe { self.value.with_mut(|v| (*v).write(f())) };
+            self.state.store(READY, Ordering::Release);
+        }
+        let backoff = Backoff::new();
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            backoff.snooze();
+        }
+    }
+}
+
+impl<T: RefUnwindSafe> RefUnwindSafe for Once<T> {}
+
+impl<T> Drop for Once<T> {
     fn drop(&mut self) {
-        let head = self.head.load(RO);
-        unsafe { drop(Box::from_raw(head)) };
+        if self.get().is_some() {
+            unsafe { self.value.with_mut(|v| (*v).assume_init_drop()) };
+        }
     }
 }
-struct Node<T> {
-    value: T,
+
+/// Decides where the nodes of a [`LockFreeCell`] live and how retired nodes are recycled.
+///
+/// Implemented by [`Boxed`], [`TlsCache`], [`Counted`], [`PerCellPool`](crate::PerCellPool)
+/// and [`PerThreadPool`](crate::PerThreadPool).
+pub trait NodeStrategy<T>: private::Sealed {
+    /// Bookkeeping stored in every node next to the value.
+    type Header;
+    /// Allocation state owned by each cell.
+    type Pool;
+    /// The cell's collector, plus for [`Inline`] the value the cell was built with.
+    type State: CellState<T>;
+    /// Batch size of the cell's collector.
+    const BATCH_SIZE: usize;
+
+    fn new_pool() -> Self::Pool;
+    /// Returns a node holding `value` that stays valid until it is passed to `release`.
+    fn alloc(pool: &Self::Pool, value: T) -> *mut Node<T, Self::Header>;
+    /// Drops the value of `node` and recycles or frees its memory.
+    ///
+    /// # Safety
+    /// `node` must come from `alloc` and be unreachable for readers.
+    unsafe fn release(node: *mut Node<T, Self::Header>);
 }
-impl<T> Node<T> {
+
+pub struct Node<T, H> {
+    pub(crate) header: H,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+impl<T, H> Node<T, H> {
+    #[inline]
+    pub(crate) fn uninit(header: H) -> Self {
+        Self {
+            header,
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+    #[inline]
+    pub(crate) unsafe fn get<'a>(node: *const Self) -> &'a T {
+        unsafe { (*node).value.with(|v| (*v).assume_init_ref()) }
+    }
+    #[inline]
+    pub(crate) unsafe fn set(node: *const Self, value: T) {
+        unsafe { (*node).value.with_mut(|v| (*v).write(value)) };
+    }
     #[inline]
-    unsafe fn get<'a>(node: *mut Node<T>) -> &'a T {
-        &unsafe { &*node }.value
+    pub(crate) unsafe fn drop_value(node: *const Self) {
+        unsafe { (*node).value.with_mut(|v| (*v).assume_init_drop()) };
     }
     #[inline]
-    unsafe fn set(node: *mut Node<T>, value: T) {
-        unsafe { &mut *node }.value = value;
+    pub(crate) fn new_boxed(header: H, value: T) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            header,
+            value: UnsafeCell::new(MaybeUninit::new(value)),
+        }))
     }
     #[inline]
-    fn new_boxed(value: T) -> *mut Node<T> {
-        Box::into_raw(Box::new(Self { value }))
+    pub(crate) unsafe fn free_boxed(node: *mut Self) {
+        unsafe {
+            Self::drop_value(node);
+            drop(Box::from_raw(node));
+        }
     }
+}
+
+/// Allocates every node with `Box`.
+pub struct Boxed;
+impl private::Sealed for Boxed {}
+impl<T> NodeStrategy<T> for Boxed {
+    type Header = ();
+    type Pool = ();
+    type State = Collector;
+    const BATCH_SIZE: usize = BATCH_SIZE;
+
+    fn new_pool() {}
     #[inline]
-    fn new_cached(value: T) -> *mut Node<T> {
-        let layout = Layout::new::<Node<T>>();
+    fn alloc(_pool: &(), value: T) -> *mut Node<T, ()> {
+        Node::new_boxed((), value)
+    }
+    #[inline]
+    unsafe fn release(node: *mut Node<T, ()>) {
+        unsafe { Node::free_boxed(node) };
+    }
+}
+
+/// Recycles node allocations through a small thread-local cache shared by all cells.
+pub struct TlsCache;
+impl private::Sealed for TlsCache {}
+impl<T> NodeStrategy<T> for TlsCache {
+    type Header = ();
+    type Pool = ();
+    type State = Collector;
+    const BATCH_SIZE: usize = BATCH_SIZE;
+
+    fn new_pool() {}
+    #[inline]
+    fn alloc(_pool: &(), value: T) -> *mut Node<T, ()> {
+        let layout = Layout::new::<Node<T, ()>>();
         NODE_CACHE.with(|c| {
             let mut slots = c.get();
             for slot in &mut slots {
                 if !slot.0.is_null() && slot.1 == layout {
-                    let ptr = slot.0 as *mut Node<T>;
+                    let ptr = slot.0 as *mut Node<T, ()>;
                     *slot = EMPTY_ENTRY;
                     c.set(slots);
-                    unsafe { ptr.write(Node { value }) };
+                    unsafe {
+                        ptr.write(Node::uninit(()));
+                        Node::set(ptr, value);
+                    }
                     return ptr;
                 }
             }
-            Node::new_boxed(value)
+            Node::new_boxed((), value)
         })
     }
+    unsafe fn release(node: *mut Node<T, ()>) {
+        unsafe { Node::drop_value(node) };
+        let layout = Layout::new::<Node<T, ()>>();
+        NODE_CACHE.with(|c| {
+            let mut slots = c.get();
+            for slot in &mut slots {
+                if slot.0.is_null() {
+                    *slot = (node as *mut u8, layout);
+                    c.set(slots);
+                    return;
+                }
+            }
+            // Cache full, deallocate
+            unsafe { std::alloc::dealloc(node as *mut u8, layout) };
+        });
+    }
 }
 
-unsafe fn cache_reclaim<T>(ptr: *mut Node<T>, _collector: &Collector) {
-    unsafe { std::ptr::drop_in_place(ptr) };
-    let layout = Layout::new::<Node<T>>();
-    NODE_CACHE.with(|c| {
-        let mut slots = c.get();
-        for slot in &mut slots {
-            if slot.0.is_null() {
-                *slot = (ptr as *mut u8, layout);
-                c.set(slots);
-                return;
-            }
+/// Boxed nodes with a reference count, so [`LockFreeCell::load_owned`] can hand out
+/// [`Snapshot`]s. The cell holds one reference until the collector reclaims the node.
+pub struct Counted;
+impl private::Sealed for Counted {}
+impl<T> NodeStrategy<T> for Counted {
+    type Header = AtomicUsize;
+    type Pool = ();
+    type State = Collector;
+    const BATCH_SIZE: usize = BATCH_SIZE;
+
+    fn new_pool() {}
+    #[inline]
+    fn alloc(_pool: &(), value: T) -> *mut Node<T, AtomicUsize> {
+        Node::new_boxed(AtomicUsize::new(1), value)
+    }
+    #[inline]
+    unsafe fn release(node: *mut Node<T, AtomicUsize>) {
+        unsafe { Snapshot::release(node) };
+    }
+}
+
+/// [`TlsCache`] nodes, but the cell starts with its value inline and makes its collector on
+/// the first write, so [`LockFreeCell::new_const`] can build it in a `static`.
+impl private::Sealed for Inline {}
+impl<T> NodeStrategy<T> for Inline {
+    type Header = ();
+    type Pool = ();
+    type State = private::InlineState<T>;
+    const BATCH_SIZE: usize = BATCH_SIZE;
+
+    fn new_pool() {}
+    #[inline]
+    fn alloc(pool: &(), value: T) -> *mut Node<T, ()> {
+        <TlsCache as NodeStrategy<T>>::alloc(pool, value)
+    }
+    unsafe fn release(node: *mut Node<T, ()>) {
+        unsafe { <TlsCache as NodeStrategy<T>>::release(node) };
+    }
+}
+
+/// An owned reference to a value a [`LockFreeCell<T, Counted>`] held. Keeps its node
+/// alive without a guard, so it can be stored or sent to another thread.
+pub struct Snapshot<T> {
+    node: ptr::NonNull<Node<T, AtomicUsize>>,
+}
+
+unsafe impl<T: Send + Sync> Send for Snapshot<T> {}
+unsafe impl<T: Send + Sync> Sync for Snapshot<T> {}
+
+impl<T> Snapshot<T> {
+    /// Drops one reference, freeing the node with the last one.
+    unsafe fn release(node: *mut Node<T, AtomicUsize>) {
+        if unsafe { &(*node).header }.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            unsafe { Node::free_boxed(node) };
         }
-        // Cache full, deallocate
-        unsafe { std::alloc::dealloc(ptr as *mut u8, layout) };
-    });
+    }
+
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.node == b.node
+    }
 }
 
-unsafe impl<T: Send> Send for LockFreeCell<T> {}
-unsafe impl<T: Send + Sync> Sync for LockFreeCell<T> {}
+impl<T> Deref for Snapshot<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { Node::get(self.node.as_ptr()) }
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Self {
+        unsafe { self.node.as_ref() }
+            .header
+            .fetch_add(1, Ordering::Relaxed);
+        Self { node: self.node }
+    }
+}
+
+impl<T> Drop for Snapshot<T> {
+    fn drop(&mut self) {
+        unsafe { Self::release(self.node.as_ptr()) };
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Snapshot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+pub(crate) unsafe fn reclaim<T, S: NodeStrategy<T>>(
+    node: *mut Node<T, S::Header>,
+    _collector: &Collector,
+) {
+    unsafe { S::release(node) };
+}
+
+/// Releases a node that never got published, e.g. when the writer's closure panics on a retry.
+pub(crate) struct Unpublished<T, S: NodeStrategy<T>>(pub(crate) *mut Node<T, S::Header>);
+impl<T, S: NodeStrategy<T>> Drop for Unpublished<T, S> {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { S::release(self.0) };
+        }
+    }
+}
+
+/// Retires a node unlinked from the head, even if reading it for the caller panics.
+struct Retire<'a, T, S: NodeStrategy<T>> {
+    cell: &'a LockFreeCell<T, S>,
+    node: *mut Node<T, S::Header>,
+}
+impl<T, S: NodeStrategy<T>> Drop for Retire<'_, T, S> {
+    fn drop(&mut self) {
+        unsafe { self.cell.retire(self.node) };
+    }
+}
+
+/// Copy-on-write cell: a panicking writer closure publishes nothing, so the cell never
+/// holds a half-updated value and needs no poisoning.
+///
+/// With the [`Inline`] strategy the head stays null while the cell holds the value it was
+/// built with, or no value at all in a [`LazyCell`](crate::LazyCell).
+pub struct LockFreeCell<T, S: NodeStrategy<T> = TlsCache> {
+    state: S::State,
+    head: CachePadded<AtomicPtr<Node<T, S::Header>>>,
+    // Declared after `state` so retired nodes are released before the pool goes away.
+    pool: S::Pool,
+    combiner: Combiner<T>,
+}
+impl<T, S: NodeStrategy<T>> Drop for LockFreeCell<T, S> {
+    fn drop(&mut self) {
+        let head = self.head.load(RO);
+        if !S::State::INLINE || !head.is_null() {
+            unsafe { S::release(head) };
+        }
+    }
+}
+
+unsafe impl<T: Send, S: NodeStrategy<T>> Send for LockFreeCell<T, S> {}
+unsafe impl<T: Send + Sync, S: NodeStrategy<T>> Sync for LockFreeCell<T, S> {}
 
 impl<T> LockFreeCell<T> {
     pub fn new(value: T) -> Self {
+        Self::with_strategy(value)
+    }
+}
+
+impl<T> LockFreeCell<T, Inline> {
+    const_fn! {
+        /// For `static`s: keeps `value` in the cell, so nothing is allocated until the first
+        /// write, which also makes the collector.
+        pub fn new_const(value: T) -> Self {
+            Self::with_inline(Some(value))
+        }
+    }
+
+    const_fn! {
+        /// No value until [`get_or_init`](Self::get_or_init) or [`store`](Self::store).
+        /// Only for [`LazyCell`](crate::LazyCell), which never reads it before that.
+        pub(crate) fn uninit() -> Self {
+            Self::with_inline(None)
+        }
+    }
+
+    const_fn! {
+        fn with_inline(value: Option<T>) -> Self {
+            Self {
+                state: private::InlineState {
+                    collector: Once::new(),
+                    value,
+                },
+                head: CachePadded::new(AtomicPtr::new(ptr::null_mut())),
+                pool: (),
+                combiner: Combiner::new(),
+            }
+        }
+    }
+
+    /// Like [`read`](Self::read), but first installs `init()` if the cell has no value
+    /// yet, which only happens inside a [`LazyCell`](crate::LazyCell). Racing first
+    /// readers may each run `init`; one value gets in and the others are dropped.
+    pub fn get_or_init<R>(&self, init: impl FnOnce() -> T, f: impl FnOnce(&T) -> R) -> R {
+        if self.head.load(RO).is_null() && self.state.value.is_none() {
+            self.state.collector();
+            let node = Inline::alloc(&(), init());
+            if self
+                .head
+                .compare_exchange(ptr::null_mut(), node, WO, Ordering::Relaxed)
+                .is_err()
+            {
+                // Never shared, so it can go right away.
+                unsafe { Inline::release(node) };
+            }
+        }
+        self.read(f)
+    }
+
+    /// Reads the value, or passes `None` while the cell has none.
+    pub(crate) fn try_read<R>(&self, f: impl FnOnce(Option<&T>) -> R) -> R {
+        if self.head.load(RO).is_null() && self.state.value.is_none() {
+            return f(None);
+        }
+        self.read(|v| f(Some(v)))
+    }
+}
+
+impl<T, S: NodeStrategy<T>> LockFreeCell<T, S> {
+    pub fn with_strategy(value: T) -> Self {
+        let pool = S::new_pool();
+        let head = S::alloc(&pool, value);
         Self {
-            collector: Collector::new().batch_size(BATCH_SIZE),
-            head: CachePadded::new(AtomicPtr::new(Node::new_boxed(value))),
+            state: S::State::ready(Collector::new().batch_size(S::BATCH_SIZE)),
+            head: CachePadded::new(AtomicPtr::new(head)),
+            pool,
+            combiner: Combiner::new(),
         }
     }
 
     #[inline]
     pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
-        let guard = self.collector.enter();
-        let head = guard.protect(&self.head, RO);
+        if S::State::INLINE && self.head.load(RO).is_null() {
+            // Nothing published yet, and the inline value never changes.
+            return f(unsafe { self.value(ptr::null()) });
+        }
+        let guard = unsafe { self.state.published() }.enter();
+        let head = protect(&guard, &self.head, RO);
         f(unsafe { Node::get(head) })
     }
 
+    /// The value of `head`, or the inline one while nothing was published.
+    ///
+    /// # Safety
+    /// `head` must be protected by a guard that outlives the returned reference.
+    #[inline(always)]
+    unsafe fn value(&self, head: *const Node<T, S::Header>) -> &T {
+        if S::State::INLINE && head.is_null() {
+            self.state
+                .inline()
+                .expect("LazyCell reads its cell before initializing it")
+        } else {
+            unsafe { Node::get(head) }
+        }
+    }
+
+    /// Retires a node unlinked from the head, unless there was none yet.
+    ///
+    /// # Safety
+    /// `node` must have been the head.
+    #[inline(always)]
+    unsafe fn retire(&self, node: *mut Node<T, S::Header>) {
+        if !S::State::INLINE || !node.is_null() {
+            unsafe { self.state.published().retire(node, reclaim::<T, S>) };
+        }
+    }
+
     /// Replace the value without reading the old one. Uses atomic swap (no CAS loop).
-    /// Uses thread-local allocation cache to avoid Box::new on every call.
     #[inline]
     pub fn store(&self, value: T) {
-        let new_ptr = Node::new_cached(value);
-        let old = self.head.swap(new_ptr, WO);
-        unsafe { self.collector.retire(old, cache_reclaim) };
+        let new_ptr = S::alloc(&self.pool, value);
+        self.state.collector();
+        // Acquire: the old node may be released on this thread, after another thread wrote it.
+        let old = self.head.swap(new_ptr, Ordering::AcqRel);
+        unsafe { self.retire(old) };
+    }
+
+    /// Replace the value and return a clone of the previous one.
+    /// Readers may still hold the old node, so it cannot be moved out.
+    pub fn swap(&self, value: T) -> T
+    where
+        T: Clone,
+    {
+        if S::State::INLINE && self.head.load(RO).is_null() {
+            // Fails before publishing anything if there is no value to return.
+            unsafe { self.value(ptr::null()) };
+        }
+        let new_ptr = S::alloc(&self.pool, value);
+        self.state.collector();
+        let old = Retire {
+            cell: self,
+            node: self.head.swap(new_ptr, Ordering::AcqRel),
+        };
+        unsafe { self.value(old.node) }.clone()
     }
 
     pub fn write_discard(&self, f: impl Fn(&T) -> T) {
-        let mut new_value = MaybeUninit::uninit();
-        let mut set = false;
-        let guard = self.collector.enter();
+        let mut new_node = Unpublished::<T, S>(ptr::null_mut());
+        let guard = self.state.collector().enter();
         loop {
-            let head = guard.protect(&self.head, RO);
-            let ptr = if set {
-                let ptr = unsafe { *new_value.as_mut_ptr() };
-                unsafe { Node::set(ptr, f(Node::get(head))) };
-                ptr
+            let head = protect(&guard, &self.head, RO);
+            let value = f(unsafe { self.value(head) });
+            if new_node.0.is_null() {
+                new_node.0 = S::alloc(&self.pool, value);
             } else {
-                new_value.write(unsafe { Node::new_boxed(f(Node::get(head))) });
-                set = true;
-                unsafe { *new_value.as_mut_ptr() }
-            };
+                unsafe {
+                    Node::drop_value(new_node.0);
+                    Node::set(new_node.0, value);
+                }
+            }
 
             if self
                 .head
-                .compare_exchange(head, ptr, WO, Ordering::Relaxed)
+                .compare_exchange(head, new_node.0, WO, Ordering::Relaxed)
                 .is_ok()
             {
-                unsafe { self.collector.retire(head, reclaim::boxed) };
+                new_node.0 = ptr::null_mut();
+                unsafe { self.retire(head) };
                 break;
             };
         }
     }
+
+    /// Like [`write_discard`](Self::write_discard), but concurrent callers hand their
+    /// closures to one of them, which applies them all to a single copy and publishes it
+    /// once. Each caller gets what its own closure returned and sees its panic.
+    ///
+    /// `f` may run several times, and on another caller's thread.
+    pub fn write_combined<R: Send>(&self, f: impl Fn(&mut T) -> R + Sync) -> R
+    where
+        T: Clone,
+    {
+        self.combiner.run(f, |batch| self.publish_batch(batch))
+    }
+
+    fn publish_batch(&self, batch: &mut Batch<T>)
+    where
+        T: Clone,
+    {
+        let mut new_node = Unpublished::<T, S>(ptr::null_mut());
+        let guard = self.state.collector().enter();
+        loop {
+            let head = protect(&guard, &self.head, RO);
+            let mut value = unsafe { self.value(head) }.clone();
+            if !batch.apply(&mut value) {
+                continue;
+            }
+            if batch.failed() {
+                return;
+            }
+            if new_node.0.is_null() {
+                new_node.0 = S::alloc(&self.pool, value);
+            } else {
+                unsafe {
+                    Node::drop_value(new_node.0);
+                    Node::set(new_node.0, value);
+                }
+            }
+            if self
+                .head
+                .compare_exchange(head, new_node.0, WO, Ordering::Relaxed)
+                .is_ok()
+            {
+                new_node.0 = ptr::null_mut();
+                batch.landed();
+                unsafe { self.retire(head) };
+                return;
+            }
+        }
+    }
+}
+
+impl<T> LockFreeCell<T, Counted> {
+    /// Returns a [`Snapshot`] of the current value that outlives the read.
+    pub fn load_owned(&self) -> Snapshot<T> {
+        let guard = self.state.enter();
+        let head = protect(&guard, &self.head, RO);
+        // The guard keeps the cell's reference, so the count is at least one.
+        unsafe { &(*head).header }.fetch_add(1, Ordering::Relaxed);
+        Snapshot {
+            node: unsafe { ptr::NonNull::new_unchecked(head) },
+        }
+    }
+}
+
+impl<T: fmt::Debug, S: NodeStrategy<T>> fmt::Debug for LockFreeCell<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read(|v| f.debug_struct("LockFreeCell").field("value", v).finish())
+    }
+}
+
+impl<T: Default, S: NodeStrategy<T>> Default for LockFreeCell<T, S> {
+    fn default() -> Self {
+        Self::with_strategy(T::default())
+    }
+}
+
+impl<T, S: NodeStrategy<T>> From<T> for LockFreeCell<T, S> {
+    fn from(value: T) -> Self {
+        Self::with_strategy(value)
+    }
+}
+
+/// A new cell holding a snapshot of the value.
+impl<T: Clone, S: NodeStrategy<T>> Clone for LockFreeCell<T, S> {
+    fn clone(&self) -> Self {
+        Self::with_strategy(self.read(T::clone))
+    }
+}
+
+/// Compares snapshots of the two values.
+impl<T: PartialEq, S: NodeStrategy<T>> PartialEq for LockFreeCell<T, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.read(|a| other.read(|b| a == b))
+    }
+}
+
+/// Serializes a snapshot taken with [`read`](LockFreeCell::read).
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, S: NodeStrategy<T>> serde::Serialize for LockFreeCell<T, S> {
+    fn serialize<Se: serde::Serializer>(&self, serializer: Se) -> Result<Se::Ok, Se::Error> {
+        self.read(|v| v.serialize(serializer))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>, S: NodeStrategy<T>> serde::Deserialize<'de>
+    for LockFreeCell<T, S>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::with_strategy)
+    }
 }
@@ -1,188 +1,143 @@
-use nohash_hasher::{IsEnabled, NoHashHasher};
-use seize::Guard;
+use nohash_hasher::NoHashHasher;
 
-use std::{
-    any::{Any, TypeId},
-    cell::UnsafeCell,
-    collections::hash_map::Entry,
-    hash::BuildHasherDefault,
-    marker::PhantomData,
-    mem::MaybeUninit,
-    sync::atomic::{AtomicPtr, Ordering},
-};
-use std::{collections::HashMap, sync::atomic::AtomicUsize};
+use std::{any::Any, cell::UnsafeCell, collections::HashMap, hash::BuildHasherDefault, ptr};
 
-use seize::Collector;
+use crate::sync::{AtomicU32, AtomicUsize, Collector, Ordering, fence, thread_local};
+use crate::sz::{Node, NodeStrategy, private::Sealed};
 
 const PRE_ALLOC_SIZE: usize = 16;
 const BATCH: usize = 12;
 
-#[derive(Eq, Hash, PartialEq, Clone, Copy)]
-struct InstanceId {
-    g_id: usize,
-    t_id: TypeId,
-}
-
-impl IsEnabled for InstanceId {}
-
-static GLOBAL_ID: AtomicUsize = AtomicUsize::new(0);
-type InstanceIdHasher = BuildHasherDefault<NoHashHasher<InstanceId>>;
-type Fm = HashMap<InstanceId, [Box<dyn Any>; PRE_ALLOC_SIZE], InstanceIdHasher>;
+// Plain std atomic: loom atomics cannot live in statics, and ids take no part in the protocol.
+static GLOBAL_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+type InstanceIdHasher = BuildHasherDefault<NoHashHasher<usize>>;
+type Fm = HashMap<usize, Box<dyn Any>, InstanceIdHasher>;
 
 thread_local! {
     static THREAD_LOCAL_NODES: UnsafeCell<Fm> = UnsafeCell::new(Fm::default());
 }
 
-#[derive(Eq, Hash, PartialEq)]
-struct PreAlloc<T> {
-    id: InstanceId,
-    _pd: PhantomData<T>,
-}
-impl<T: 'static> PreAlloc<T> {
-    fn new() -> Self {
-        Self {
-            id: InstanceId {
-                g_id: GLOBAL_ID.fetch_add(1, Ordering::Relaxed),
-                t_id: TypeId::of::<T>(),
-            },
-            _pd: PhantomData,
-        }
-    }
-    fn set(&self, value: T) -> *mut Node<T> {
-        THREAD_LOCAL_NODES.with(|v| {
-            let map = unsafe { &mut *v.get() };
-            match map.entry(self.id) {
-                Entry::Occupied(mut entry) => {
-                    let nodes = entry.get_mut();
-                    for node in nodes {
-                        let node = node.downcast_mut::<Node<T>>().unwrap();
-                        if node.write_locked.try_write() {
-                            return unsafe { node.set(value) };
-                        }
-                    }
-                    Node::new_raw(value)
-                }
-                Entry::Vacant(entry) => {
-                    let mut a: [Box<dyn Any + 'static>; PRE_ALLOC_SIZE] =
-                        std::array::from_fn(|_| Node::<T>::new_uninit_local() as Box<dyn Any>);
-                    let ptr = unsafe { a[0].downcast_mut::<Node<T>>().unwrap().set(value) };
-                    entry.insert(a);
-                    ptr
-                }
-            }
-        })
-    }
-}
+/// Keeps 16 nodes per thread per cell in a thread-local map, falling back to `Box` while all of
+/// them are in flight.
+pub struct PerThreadPool;
 
-enum WriteLock {
-    Local(LockState),
-    Boxed,
-}
 #[repr(u32)]
 enum LockState {
     Available = 0,
     Writing = 1,
     ReadLocked = 2,
 }
-impl WriteLock {
-    fn try_write(&mut self) -> bool {
-        match self {
-            WriteLock::Local(state) => match state {
-                LockState::Available => {
-                    *state = LockState::Writing;
-                    true
-                }
-                _ => false,
-            },
-            WriteLock::Boxed => unreachable!(),
-        }
-    }
-    fn read_lock(&mut self) {
-        match self {
-            WriteLock::Local(state) => *state = LockState::ReadLocked,
-            WriteLock::Boxed => unreachable!(),
-        }
-    }
+
+pub struct ThreadSlot<T> {
+    state: AtomicU32,
+    // Null for boxed nodes.
+    block: *const Block<T>,
 }
 
-struct Node<T> {
-    write_locked: WriteLock,
-    value: UnsafeCell<MaybeUninit<T>>,
+/// Nodes of one thread for one cell. Freed once the owning thread has exited and
+/// every node handed out from it has been released, whichever happens last.
+struct Block<T> {
+    refs: AtomicUsize,
+    nodes: [Node<T, ThreadSlot<T>>; PRE_ALLOC_SIZE],
 }
-impl<T> Node<T> {
-    unsafe fn get<'a>(node: *mut Node<T>) -> &'a T {
-        let node = unsafe { &*node };
-        unsafe { &*(*node.value.get()).as_ptr() }
-    }
 
-    fn new_uninit_local() -> Box<Node<T>> {
-        Box::new(Self {
-            write_locked: WriteLock::Local(LockState::Available),
-            value: UnsafeCell::new(MaybeUninit::uninit()),
-        })
+impl<T> Block<T> {
+    fn new() -> *const Block<T> {
+        let block = Box::into_raw(Box::new(Block {
+            refs: AtomicUsize::new(1),
+            nodes: std::array::from_fn(|_| {
+                Node::uninit(ThreadSlot {
+                    state: AtomicU32::new(LockState::Available as u32),
+                    block: ptr::null(),
+                })
+            }),
+        }));
+        for node in unsafe { &mut (*block).nodes } {
+            node.header.block = block;
+        }
+        block
     }
-    fn new_raw(value: T) -> *mut Node<T> {
-        Box::into_raw(Box::new(Self {
-            write_locked: WriteLock::Boxed,
-            value: UnsafeCell::new(MaybeUninit::new(value)),
-        }))
+    unsafe fn alloc(block: *const Block<T>, value: T) -> *mut Node<T, ThreadSlot<T>> {
+        let b = unsafe { &*block };
+        for node in &b.nodes {
+            if node
+                .header
+                .state
+                .compare_exchange(
+                    LockState::Available as u32,
+                    LockState::Writing as u32,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                b.refs.fetch_add(1, Ordering::Relaxed);
+                let ptr = node as *const Node<T, ThreadSlot<T>> as *mut Node<T, ThreadSlot<T>>;
+                unsafe { Node::set(ptr, value) };
+                node.header
+                    .state
+                    .store(LockState::ReadLocked as u32, Ordering::Relaxed);
+                return ptr;
+            }
+        }
+        Node::new_boxed(
+            ThreadSlot {
+                state: AtomicU32::new(LockState::ReadLocked as u32),
+                block: ptr::null(),
+            },
+            value,
+        )
     }
-    unsafe fn set(&mut self, value: T) -> *mut Node<T> {
-        self.value.get_mut().write(value);
-        self.write_locked.read_lock();
-        self as *const Node<T> as *mut Node<T>
+    unsafe fn release_ref(block: *const Block<T>) {
+        if unsafe { &*block }.refs.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            drop(unsafe { Box::from_raw(block as *mut Block<T>) });
+        }
     }
 }
 
-unsafe impl<T: Send> Send for LockFreeCell<T> {}
-unsafe impl<T: Send + Sync> Sync for LockFreeCell<T> {}
-
-pub struct LockFreeCell<T> {
-    pre_alloc: PreAlloc<T>,
-
-    collector: Collector,
-
-    head: AtomicPtr<Node<T>>,
+/// The owning thread's reference to its block, dropped on thread exit.
+struct BlockHandle<T>(*const Block<T>);
+impl<T> Drop for BlockHandle<T> {
+    fn drop(&mut self) {
+        unsafe { Block::release_ref(self.0) };
+    }
 }
 
-impl<T: 'static> LockFreeCell<T> {
-    pub fn new(value: T) -> Self {
-        let pre_alloc = PreAlloc::new();
-        let ptr = pre_alloc.set(value);
-        Self {
-            pre_alloc,
-            collector: Collector::new().batch_size(BATCH),
-            head: AtomicPtr::new(ptr),
-        }
+impl Sealed for PerThreadPool {}
+impl<T: 'static> NodeStrategy<T> for PerThreadPool {
+    type Header = ThreadSlot<T>;
+    /// Id of the cell in the thread-local map.
+    type Pool = usize;
+    type State = Collector;
+    const BATCH_SIZE: usize = BATCH;
+
+    fn new_pool() -> usize {
+        GLOBAL_ID.fetch_add(1, Ordering::Relaxed)
     }
-    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
-        let guard = self.collector.enter();
-        let head = guard.protect(&self.head, Ordering::Relaxed);
-        f(unsafe { Node::get(head) })
+    fn alloc(id: &usize, value: T) -> *mut Node<T, ThreadSlot<T>> {
+        THREAD_LOCAL_NODES.with(|v| {
+            let map = unsafe { &mut *v.get() };
+            let handle = map
+                .entry(*id)
+                .or_insert_with(|| Box::new(BlockHandle(Block::<T>::new())));
+            let block = handle.downcast_ref::<BlockHandle<T>>().unwrap().0;
+            unsafe { Block::alloc(block, value) }
+        })
     }
-
-    pub fn write_discard(&self, f: impl FnOnce(&T) -> T) {
-        let guard = self.collector.enter();
-        let head = guard.protect(&self.head, Ordering::Relaxed);
-        let new_val = unsafe { f(Node::get(head)) };
-        let new_head = self.pre_alloc.set(new_val);
-        self.head.swap(new_head, Ordering::Relaxed);
-        drop(guard);
+    unsafe fn release(node: *mut Node<T, ThreadSlot<T>>) {
+        let block = unsafe { (*node).header.block };
+        if block.is_null() {
+            unsafe { Node::free_boxed(node) };
+            return;
+        }
         unsafe {
-            self.collector
-                .retire(head, |value: *mut Node<T>, _collector: &Collector| {
-                    // Safety: The value was allocated with `Box::new`.
-                    let value = &mut *value;
-                    match &mut value.write_locked {
-                        WriteLock::Local(u) => {
-                            *u = LockState::Available;
-                        }
-                        WriteLock::Boxed => {
-                            let value = Box::from_raw(value);
-                            drop(value);
-                        }
-                    }
-                })
-        };
+            Node::drop_value(node);
+            (*node)
+                .header
+                .state
+                .store(LockState::Available as u32, Ordering::Release);
+            Block::release_ref(block);
+        }
     }
 }
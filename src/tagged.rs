@@ -1,73 +1,496 @@
-use crossbeam_utils::{Backoff, CachePadded};
-use std::{
-    cell::UnsafeCell,
-    sync::atomic::{AtomicUsize, Ordering},
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut},
+    panic::RefUnwindSafe,
+    ptr::{self, NonNull},
 };
-use std::{marker::PhantomData, ptr::NonNull};
-/// Assumes T has at least 8-byte alignment ⇒ 3 bits free
-const PTR_MASK: usize = !0b111;
-const READER_MASK: usize = 0b111;
+use crossbeam_utils::CachePadded;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
-#[repr(align(8))]
+use crate::sync::{
+    AtomicBool, AtomicU32, AtomicUsize, Backoff, Ordering, UnsafeCell, const_fn, fence, panicking,
+};
+
+mod private {
+    use super::{MaybeUninit, RefUnwindSafe, UnsafeCell};
+
+    pub trait Sealed {}
+
+    /// Where an [`Inline`](super::Inline) cell keeps its value while its address is 0.
+    pub struct InlineSlot<T>(pub(super) UnsafeCell<MaybeUninit<T>>);
+
+    // Only reached under the cell's lock, like the boxed value.
+    impl<T: RefUnwindSafe> RefUnwindSafe for InlineSlot<T> {}
+}
+
+/// Where a [`SpinCell`] keeps its reader count.
+///
+/// The reader field saturated to `READER_MASK` is the writer sentinel,
+/// so a layout fits `READER_MASK - 1` concurrent readers.
+pub trait CountLayout: private::Sealed {
+    type State;
+    /// Room for a value kept in the cell itself: `()` except for [`Inline`].
+    type Slot<T>;
+    /// Address 0 means the value is in the slot.
+    const INLINE: bool;
+    const READER_MASK: usize;
+    /// Set while a writer waits for readers to drain.
+    const WAITING: usize;
+    /// [`Fairness::PhaseFair`]: set by readers blocked behind a writer.
+    const QUEUED: usize;
+    /// [`Fairness::PhaseFair`]: queued readers may pass a waiting writer.
+    const TURN: usize;
+    /// Held by the single upgradable reader, which also counts as a reader.
+    const UPGRADER: usize;
+
+    fn new_state(addr: usize) -> Self::State;
+    fn word(state: &Self::State) -> &AtomicUsize;
+    /// Address of the data, valid while a read or write lock taken on `word` is held.
+    fn addr(state: &Self::State, word: usize) -> usize;
+    /// Points the cell at `new` instead of `old`. Only called under the write lock.
+    fn set_addr(state: &Self::State, old: usize, new: usize);
+    /// Panics if the layout cannot store `addr`. Called on every new box before it is
+    /// handed to `new_state` or `set_addr`, so no lock is held if it fails.
+    fn check_addr(_addr: usize) {}
+
+    fn empty_slot<T>() -> Self::Slot<T>;
+    /// The slot of an [`INLINE`](Self::INLINE) layout; unreachable for the others.
+    fn inline<T>(slot: &Self::Slot<T>) -> &private::InlineSlot<T>;
+}
+
+/// Reader count in the low 3 bits of the data pointer: one word, at most 6 readers.
+///
+/// On x86_64 and aarch64 the flags take bits 52..56: user addresses there fit in 48 bits
+/// (52 with aarch64 large VAs, still below the top-byte tag), and x86_64 5-level paging
+/// only hands out higher ones to an explicit `mmap` hint. Other targets are not assumed to
+/// leave any high bits free, so the flags sit in the low bits too and boxed values are
+/// 128-byte aligned to make room. A pointer overlapping the flags fails an assert.
+pub struct Packed;
+impl private::Sealed for Packed {}
+impl Packed {
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const FLAG: usize = 1 << 52;
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const FLAG: usize = 1 << 3;
+    const TAG_MASK: usize = Self::READER_MASK | (0b1111 * Self::FLAG);
+}
+impl CountLayout for Packed {
+    type State = AtomicUsize;
+    type Slot<T> = ();
+    const INLINE: bool = false;
+    const READER_MASK: usize = 0b111;
+    const WAITING: usize = Self::FLAG;
+    const QUEUED: usize = Self::FLAG << 1;
+    const TURN: usize = Self::FLAG << 2;
+    const UPGRADER: usize = Self::FLAG << 3;
+
+    fn new_state(addr: usize) -> AtomicUsize {
+        AtomicUsize::new(addr)
+    }
+    #[inline(always)]
+    fn word(state: &AtomicUsize) -> &AtomicUsize {
+        state
+    }
+    #[inline(always)]
+    fn addr(_state: &AtomicUsize, word: usize) -> usize {
+        word & !Self::TAG_MASK
+    }
+    #[inline(always)]
+    fn set_addr(state: &AtomicUsize, old: usize, new: usize) {
+        // Flips only address bits, so flags set concurrently by waiting threads survive.
+        state.fetch_xor(old ^ new, Ordering::Relaxed);
+    }
+    fn check_addr(addr: usize) {
+        assert_eq!(
+            addr & Self::TAG_MASK,
+            0,
+            "address overlaps SpinCell flag bits"
+        );
+    }
+    fn empty_slot<T>() {}
+    fn inline<T>(_slot: &()) -> &private::InlineSlot<T> {
+        unreachable!()
+    }
+}
+
+/// Reader count in a word of its own next to the data pointer: about `usize::MAX / 16` readers.
+pub struct Split;
+impl private::Sealed for Split {}
+pub struct SplitState {
+    word: AtomicUsize,
+    addr: AtomicUsize,
+}
+impl CountLayout for Split {
+    type State = SplitState;
+    type Slot<T> = ();
+    const INLINE: bool = false;
+    const READER_MASK: usize = usize::MAX >> 4;
+    const WAITING: usize = 1 << (usize::BITS - 1);
+    const QUEUED: usize = 1 << (usize::BITS - 2);
+    const TURN: usize = 1 << (usize::BITS - 3);
+    const UPGRADER: usize = 1 << (usize::BITS - 4);
+
+    fn new_state(addr: usize) -> SplitState {
+        SplitState {
+            word: AtomicUsize::new(0),
+            addr: AtomicUsize::new(addr),
+        }
+    }
+    #[inline(always)]
+    fn word(state: &SplitState) -> &AtomicUsize {
+        &state.word
+    }
+    #[inline(always)]
+    fn addr(state: &SplitState, _word: usize) -> usize {
+        state.addr.load(Ordering::Relaxed)
+    }
+    #[inline(always)]
+    fn set_addr(state: &SplitState, _old: usize, new: usize) {
+        state.addr.store(new, Ordering::Relaxed);
+    }
+    fn empty_slot<T>() {}
+    fn inline<T>(_slot: &()) -> &private::InlineSlot<T> {
+        unreachable!()
+    }
+}
+
+/// Keeps a cell's first value in the cell itself, so `new_const` can build it in a
+/// `static` without allocating.
+///
+/// As a [`SpinCell`] layout it is [`Packed`] plus that slot: writes in place keep using it,
+/// and the first `store`, `swap` or `replace_with` boxes its value. As a
+/// [`LockFreeCell`](crate::LockFreeCell) strategy it allocates like `TlsCache`, and the
+/// first write also makes the cell's collector.
+pub struct Inline;
+impl private::Sealed for Inline {}
+impl CountLayout for Inline {
+    type State = AtomicUsize;
+    type Slot<T> = private::InlineSlot<T>;
+    const INLINE: bool = true;
+    const READER_MASK: usize = Packed::READER_MASK;
+    const WAITING: usize = Packed::WAITING;
+    const QUEUED: usize = Packed::QUEUED;
+    const TURN: usize = Packed::TURN;
+    const UPGRADER: usize = Packed::UPGRADER;
+
+    fn new_state(addr: usize) -> AtomicUsize {
+        Packed::new_state(addr)
+    }
+    #[inline(always)]
+    fn word(state: &AtomicUsize) -> &AtomicUsize {
+        state
+    }
+    #[inline(always)]
+    fn addr(state: &AtomicUsize, word: usize) -> usize {
+        Packed::addr(state, word)
+    }
+    #[inline(always)]
+    fn set_addr(state: &AtomicUsize, old: usize, new: usize) {
+        Packed::set_addr(state, old, new)
+    }
+    fn check_addr(addr: usize) {
+        Packed::check_addr(addr)
+    }
+    fn empty_slot<T>() -> private::InlineSlot<T> {
+        private::InlineSlot(UnsafeCell::new(MaybeUninit::uninit()))
+    }
+    #[inline(always)]
+    fn inline<T>(slot: &private::InlineSlot<T>) -> &private::InlineSlot<T> {
+        slot
+    }
+}
+
+/// Order in which a [`SpinCell`] lets readers and writers in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fairness {
+    /// Readers ignore waiting writers, so a steady stream of readers can starve them.
+    /// The only mode where a thread may take a read lock while it already holds one.
+    #[default]
+    ReaderPreferring,
+    /// A waiting writer blocks new readers, so a nested read deadlocks once a writer
+    /// queues between the outer and the inner one.
+    WriterPreferring,
+    /// Readers and writers alternate: readers blocked by a writer may enter right after it,
+    /// even if the next writer is already waiting. Nested reads can deadlock as in
+    /// [`WriterPreferring`](Self::WriterPreferring).
+    PhaseFair,
+}
+
+/// What a blocked [`SpinCell`] access does once spinning stops paying off.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// Spin forever.
+    #[default]
+    Spin,
+    /// Spin, then sleep on a futex (or the platform equivalent) until the cell is released.
+    /// Without the `std` feature there is nothing to sleep on, so this keeps spinning.
+    Park,
+}
+
+/// Returned when a [`SpinCell`] access gives up instead of waiting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WouldBlock;
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SpinCell access would block")
+    }
+}
+
+impl core::error::Error for WouldBlock {}
+
+/// How long a lock attempt may wait.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Patience {
+    Forever,
+    Never,
+    #[cfg(feature = "std")]
+    Until(Instant),
+}
+
+/// Sleepers wait on `epoch`, which every release bumps while `parked != 0`.
+struct Parker {
+    epoch: AtomicU32,
+    parked: AtomicU32,
+}
+
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(8)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(128))
+)]
 /// Reference-counted data with atomic ref count
 struct RefCountedData<T> {
     data: UnsafeCell<T>,
 }
 
 impl<T> RefCountedData<T> {
-    fn new(data: T) -> *mut Self {
+    fn new<L: CountLayout>(data: T) -> *mut Self {
         let v = Box::new(Self {
             data: UnsafeCell::new(data),
         });
+        L::check_addr(&*v as *const Self as usize);
         Box::into_raw(v)
     }
 }
 
-pub struct SpinCell<T> {
-    inner: CachePadded<AtomicUsize>, // tagged pointer + reader count
+pub struct SpinCell<T, L: CountLayout = Packed> {
+    inner: CachePadded<L::State>, // tagged pointer + reader count
+    fairness: Fairness,
+    wait: WaitStrategy,
+    parker: Parker,
+    poisoning: bool,
+    poisoned: AtomicBool,
+    /// The value of a [`new_const`](SpinCell::new_const) cell while its address is 0.
+    slot: L::Slot<T>,
     _pd: PhantomData<T>,
 }
 
 impl<T> SpinCell<T> {
     pub fn new(value: T) -> Self {
-        let ptr = RefCountedData::new(value);
-        debug_assert_eq!((ptr as usize) & READER_MASK, 0);
+        Self::with_layout(value)
+    }
+}
+
+impl<T> SpinCell<T, Inline> {
+    const_fn! {
+        /// For `static`s: keeps `value` inline (address 0) until a `store`, `swap` or
+        /// `replace_with` boxes its replacement. Writes in place never allocate.
+        pub fn new_const(value: T) -> Self {
+            Self {
+                inner: CachePadded::new(AtomicUsize::new(0)),
+                fairness: Fairness::ReaderPreferring,
+                wait: WaitStrategy::Spin,
+                parker: Parker {
+                    epoch: AtomicU32::new(0),
+                    parked: AtomicU32::new(0),
+                },
+                poisoning: false,
+                poisoned: AtomicBool::new(false),
+                slot: private::InlineSlot(UnsafeCell::new(MaybeUninit::new(value))),
+                _pd: PhantomData,
+            }
+        }
+    }
+}
+
+impl<T, L: CountLayout> SpinCell<T, L> {
+    pub fn with_layout(value: T) -> Self {
+        let ptr = RefCountedData::new::<L>(value);
         debug_assert!(align_of::<Box<RefCountedData<T>>>() > 3);
 
         let addr = ptr as usize;
         Self {
-            inner: CachePadded::new(AtomicUsize::new(addr)),
+            inner: CachePadded::new(L::new_state(addr)),
+            fairness: Fairness::default(),
+            wait: WaitStrategy::default(),
+            parker: Parker {
+                epoch: AtomicU32::new(0),
+                parked: AtomicU32::new(0),
+            },
+            poisoning: false,
+            poisoned: AtomicBool::new(false),
+            slot: L::empty_slot(),
             _pd: PhantomData,
         }
     }
+    pub const fn fairness(mut self, fairness: Fairness) -> Self {
+        self.fairness = fairness;
+        self
+    }
+    pub const fn wait_strategy(mut self, wait: WaitStrategy) -> Self {
+        self.wait = wait;
+        self
+    }
+    /// Marks the cell poisoned when a writer panics, like `std::sync::Mutex`.
+    /// Accesses keep working; check [`is_poisoned`](Self::is_poisoned) to detect a
+    /// value a panicking writer may have left half-updated. Needs the `std` feature to
+    /// notice the panic; without it the cell is never poisoned.
+    pub const fn poisoning(mut self, enabled: bool) -> Self {
+        self.poisoning = enabled;
+        self
+    }
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::Relaxed);
+    }
+    /// Waits for the lock word to move on from `observed`.
+    #[inline(always)]
+    fn relax(&self, backoff: &Backoff, observed: usize) {
+        match self.wait {
+            WaitStrategy::Spin => backoff.spin(),
+            // `spin` never completes the backoff; `snooze` does, yielding on the way.
+            WaitStrategy::Park if backoff.is_completed() => self.park(observed),
+            WaitStrategy::Park => backoff.snooze(),
+        }
+    }
+    #[cold]
+    fn park(&self, observed: usize) {
+        self.parker.parked.fetch_add(1, Ordering::SeqCst);
+        let epoch = self.parker.epoch.load(Ordering::SeqCst);
+        // Pairs with the fence in `unpark`: either the releaser sees us parked,
+        // or we see its release here and do not sleep.
+        fence(Ordering::SeqCst);
+        if self.word().load(Ordering::Relaxed) == observed {
+            crate::sync::wait(&self.parker.epoch, epoch);
+        }
+        self.parker.parked.fetch_sub(1, Ordering::Relaxed);
+    }
+    /// Whether readers queued behind the last writer may still pass a waiting one.
+    #[cfg(all(test, feature = "std", not(loom)))]
+    pub(crate) fn reader_turn(&self) -> bool {
+        self.word().load(Ordering::Relaxed) & L::TURN != 0
+    }
+    /// Threads currently asleep in `park`.
+    #[cfg(all(test, feature = "std", not(loom)))]
+    pub(crate) fn parked(&self) -> u32 {
+        self.parker.parked.load(Ordering::Relaxed)
+    }
+    #[inline(always)]
+    fn unpark(&self) {
+        if self.wait == WaitStrategy::Park {
+            fence(Ordering::SeqCst);
+            if self.parker.parked.load(Ordering::Relaxed) != 0 {
+                self.parker.epoch.fetch_add(1, Ordering::SeqCst);
+                crate::sync::wake_all(&self.parker.epoch);
+            }
+        }
+    }
+    #[inline(always)]
+    fn word(&self) -> &AtomicUsize {
+        L::word(&self.inner)
+    }
+    /// The boxed data, or `None` while the value is inline.
+    #[inline(always)]
+    fn data(&self, word: usize) -> Option<NonNull<RefCountedData<T>>> {
+        let addr = L::addr(&self.inner, word) as *mut RefCountedData<T>;
+        if L::INLINE {
+            NonNull::new(addr)
+        } else {
+            Some(unsafe { NonNull::new_unchecked(addr) })
+        }
+    }
+    #[inline(always)]
+    fn value(&self, word: usize) -> NonNull<T> {
+        match self.data(word) {
+            Some(data) => unsafe { data.as_ref() }
+                .data
+                .with(|p| unsafe { NonNull::new_unchecked(p as *mut T) }),
+            None => L::inline(&self.slot)
+                .0
+                .with(|p| unsafe { NonNull::new_unchecked(p as *mut T) }),
+        }
+    }
+    /// Like [`value`](Self::value), for the write lock holder.
+    #[inline(always)]
+    fn value_mut(&self, word: usize) -> NonNull<T> {
+        match self.data(word) {
+            Some(data) => unsafe { data.as_ref() }
+                .data
+                .with_mut(|p| unsafe { NonNull::new_unchecked(p) }),
+            None => L::inline(&self.slot)
+                .0
+                .with_mut(|p| unsafe { NonNull::new_unchecked(p.cast()) }),
+        }
+    }
+    /// Moves the value out of `data`, which the cell no longer points to.
+    unsafe fn take(&self, data: Option<NonNull<RefCountedData<T>>>) -> T {
+        match data {
+            Some(data) => unsafe { Box::from_raw(data.as_ptr()) }.data.into_inner(),
+            None => L::inline(&self.slot)
+                .0
+                .with_mut(|p| unsafe { (*p).assume_init_read() }),
+        }
+    }
     #[inline(always)]
     pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        match self.lock_read(Patience::Forever) {
+            Ok(word) => self.read_locked(word, f),
+            Err(WouldBlock) => unreachable!(),
+        }
+    }
+    /// Reads only if no writer holds or waits for the cell.
+    #[inline(always)]
+    pub fn try_read<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, WouldBlock> {
+        let word = self.lock_read(Patience::Never)?;
+        Ok(self.read_locked(word, f))
+    }
+    /// Like [`read`](Self::read), but gives up after `timeout`. Never parks.
+    #[cfg(feature = "std")]
+    pub fn read_timeout<R>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(&T) -> R,
+    ) -> Result<R, WouldBlock> {
+        let word = self.lock_read(Patience::Until(Instant::now() + timeout))?;
+        Ok(self.read_locked(word, f))
+    }
+    #[inline(always)]
+    fn lock_read(&self, patience: Patience) -> Result<usize, WouldBlock> {
         let backoff = Backoff::new();
         loop {
-            let old = self.inner.load(Ordering::Acquire);
-            let (addr, count) = Self::unpack(old);
+            let old = self.word().load(Ordering::Acquire);
+            let count = Self::readers(old);
 
-            if count == READER_MASK {
-                // std::hint::spin_loop();
-                backoff.spin();
+            if self.reader_blocked(old) || count >= L::READER_MASK - 1 {
+                self.wait(&backoff, old, patience)?;
                 continue;
             }
 
-            let new = Self::pack(addr, count + 1);
-
-            match self
-                .inner
-                .compare_exchange_weak(old, new, Ordering::Release, Ordering::Relaxed)
-            {
-                Ok(_) => {
-                    let ptr = unsafe { NonNull::new_unchecked(addr as *mut RefCountedData<T>) };
-                    let ref_data = unsafe { ptr.as_ref() };
-                    let result = f(unsafe { ref_data.data.get().as_ref().unwrap() });
-                    self.inner.fetch_sub(1, Ordering::Release);
-                    return result;
-                }
+            match self.word().compare_exchange_weak(
+                old,
+                old + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(old),
                 Err(_) => {
-                    // std::hint::spin_loop();
                     backoff.spin();
                     continue;
                 }
@@ -75,64 +498,456 @@ impl<T> SpinCell<T> {
         }
     }
     #[inline(always)]
-    fn unpack(value: usize) -> (usize, usize) {
-        (value & PTR_MASK, value & READER_MASK)
+    fn read_locked<R>(&self, word: usize, f: impl FnOnce(&T) -> R) -> R {
+        // Unlocks on drop, also when `f` panics.
+        let guard = SpinReadGuard {
+            cell: self,
+            data: self.value(word),
+        };
+        f(&guard)
     }
     #[inline(always)]
-    fn pack(addr: usize, readers: usize) -> usize {
-        debug_assert!(readers <= READER_MASK);
-        debug_assert_eq!(addr & READER_MASK, 0);
-        addr | readers
+    fn read_unlock(&self) {
+        self.word().fetch_sub(1, Ordering::Release);
+        self.unpark();
+    }
+    /// Holds a read lock until the guard is dropped.
+    pub fn read_guard(&self) -> SpinReadGuard<'_, T, L> {
+        match self.lock_read(Patience::Forever) {
+            Ok(word) => SpinReadGuard {
+                cell: self,
+                data: self.value(word),
+            },
+            Err(WouldBlock) => unreachable!(),
+        }
+    }
+    #[inline(always)]
+    fn wait(
+        &self,
+        backoff: &Backoff,
+        observed: usize,
+        patience: Patience,
+    ) -> Result<(), WouldBlock> {
+        match patience {
+            Patience::Forever => self.relax(backoff, observed),
+            Patience::Never => return Err(WouldBlock),
+            #[cfg(feature = "std")]
+            Patience::Until(deadline) => {
+                if Instant::now() >= deadline {
+                    return Err(WouldBlock);
+                }
+                backoff.snooze();
+            }
+        }
+        Ok(())
+    }
+    #[inline(always)]
+    fn readers(value: usize) -> usize {
+        value & L::READER_MASK
+    }
+    #[inline(always)]
+    fn reader_blocked(&self, word: usize) -> bool {
+        match self.fairness {
+            Fairness::ReaderPreferring => false,
+            Fairness::WriterPreferring => word & L::WAITING != 0,
+            Fairness::PhaseFair => {
+                let blocked = Self::readers(word) == L::READER_MASK
+                    || (word & L::WAITING != 0 && word & L::TURN == 0);
+                if blocked && word & L::QUEUED == 0 {
+                    self.word().fetch_or(L::QUEUED, Ordering::Relaxed);
+                }
+                blocked
+            }
+        }
+    }
+    /// Gives readers queued behind the previous writer a bounded window to enter
+    /// before this writer starts blocking new readers.
+    fn end_reader_turn(&self) {
+        let backoff = Backoff::new();
+        loop {
+            let word = self.word().load(Ordering::Relaxed);
+            if word & L::TURN == 0 {
+                return;
+            }
+            if Self::readers(word) != 0 || backoff.is_completed() {
+                self.word().fetch_and(!L::TURN, Ordering::Relaxed);
+                return;
+            }
+            // Yields, so queued readers get in on a single core too, and completes.
+            backoff.snooze();
+        }
+    }
+    fn write_unlock(&self) {
+        if self.fairness == Fairness::PhaseFair {
+            let _ = self
+                .word()
+                .fetch_update(Ordering::Release, Ordering::Relaxed, |word| {
+                    let word = word - L::READER_MASK;
+                    Some(if word & L::QUEUED != 0 {
+                        (word & !L::QUEUED) | L::TURN
+                    } else {
+                        word
+                    })
+                });
+        } else {
+            self.word().fetch_sub(L::READER_MASK, Ordering::Release);
+        }
+        self.unpark();
     }
 
     #[inline(always)]
     pub fn write_discard(&self, f: impl FnOnce(&mut T)) {
+        match self.lock_write(Patience::Forever) {
+            Ok(word) => self.write_locked(word, f),
+            Err(WouldBlock) => unreachable!(),
+        }
+    }
+    /// Writes only if there are no readers or other writers.
+    #[inline(always)]
+    pub fn try_write<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, WouldBlock> {
+        let word = self.lock_write(Patience::Never)?;
+        Ok(self.write_locked(word, f))
+    }
+    /// Like [`write_discard`](Self::write_discard), but gives up after `timeout`. Never parks.
+    #[cfg(feature = "std")]
+    pub fn write_timeout<R>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, WouldBlock> {
+        let word = self.lock_write(Patience::Until(Instant::now() + timeout))?;
+        Ok(self.write_locked(word, f))
+    }
+    #[inline(always)]
+    fn lock_write(&self, patience: Patience) -> Result<usize, WouldBlock> {
+        // A `try_write` skips the window: it may not get the lock, and then the turn
+        // must not end. Taking the lock ends it either way.
+        if self.fairness == Fairness::PhaseFair && patience != Patience::Never {
+            self.end_reader_turn();
+        }
         let backoff = Backoff::new();
+        let mut announced = false;
         loop {
-            let current = self.inner.load(Ordering::Acquire);
-            let (addr, readers) = Self::unpack(current);
+            let current = self.word().load(Ordering::Acquire);
 
-            if readers != 0 {
-                // std::hint::spin_loop();
-                backoff.spin();
+            if Self::readers(current) != 0 {
+                if self.fairness != Fairness::ReaderPreferring
+                    && current & L::WAITING == 0
+                    && patience != Patience::Never
+                {
+                    self.word().fetch_or(L::WAITING, Ordering::Relaxed);
+                    announced = true;
+                    continue;
+                }
+                if let Err(e) = self.wait(&backoff, current, patience) {
+                    if announced {
+                        // Other waiting writers set it again on their next round.
+                        self.word().fetch_and(!L::WAITING, Ordering::Relaxed);
+                        self.unpark();
+                    }
+                    return Err(e);
+                }
                 continue;
             }
 
-            let new = Self::pack(addr, READER_MASK);
-
-            match self.inner.compare_exchange_weak(
+            match self.word().compare_exchange_weak(
                 current,
-                new,
-                Ordering::Release,
+                (current & !(L::WAITING | L::TURN)) | L::READER_MASK,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(current),
+                Err(_) => {
+                    backoff.spin();
+                    continue;
+                }
+            }
+        }
+    }
+    /// Holds a read lock that can later be upgraded to the write lock without letting
+    /// another writer in. Coexists with plain readers, but only one upgradable reader
+    /// is admitted at a time.
+    pub fn upgradable_read(&self) -> SpinUpgradableGuard<'_, T, L> {
+        let backoff = Backoff::new();
+        loop {
+            let old = self.word().load(Ordering::Acquire);
+            let count = Self::readers(old);
+
+            if old & L::UPGRADER != 0 || self.reader_blocked(old) || count >= L::READER_MASK - 1 {
+                self.relax(&backoff, old);
+                continue;
+            }
+
+            match self.word().compare_exchange_weak(
+                old,
+                (old + 1) | L::UPGRADER,
+                Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
                 Ok(_) => {
-                    let mut ptr = unsafe { NonNull::new_unchecked(addr as *mut RefCountedData<T>) };
-                    let data = unsafe { ptr.as_mut() };
-                    f(data.data.get_mut());
-                    self.inner.fetch_sub(7, Ordering::Release);
-                    return;
+                    return SpinUpgradableGuard {
+                        cell: self,
+                        data: self.value(old),
+                    };
                 }
                 Err(_) => {
-                    // std::hint::spin_loop();
                     backoff.spin();
                     continue;
                 }
             }
         }
     }
+    /// Waits until the upgradable reader is the only reader left, then turns its
+    /// read lock into the write lock in one step.
+    fn upgrade_lock(&self) {
+        if self.fairness == Fairness::PhaseFair {
+            self.end_reader_turn();
+        }
+        let backoff = Backoff::new();
+        loop {
+            let current = self.word().load(Ordering::Acquire);
+
+            if Self::readers(current) != 1 {
+                if self.fairness != Fairness::ReaderPreferring && current & L::WAITING == 0 {
+                    self.word().fetch_or(L::WAITING, Ordering::Relaxed);
+                    continue;
+                }
+                self.relax(&backoff, current);
+                continue;
+            }
+
+            match self.word().compare_exchange_weak(
+                current,
+                ((current - 1) & !(L::WAITING | L::UPGRADER)) | L::READER_MASK,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(_) => {
+                    backoff.spin();
+                    continue;
+                }
+            }
+        }
+    }
+    /// Replaces the whole value. The new value is boxed before the write lock is taken
+    /// and the old one is dropped after it is released.
+    pub fn store(&self, value: T) {
+        drop(self.swap(value));
+    }
+    /// Like [`store`](Self::store), but returns the old value.
+    pub fn swap(&self, value: T) -> T {
+        let new = RefCountedData::new::<L>(value);
+        match self.lock_write(Patience::Forever) {
+            Ok(word) => {
+                let old = self.data(word);
+                L::set_addr(&self.inner, L::addr(&self.inner, word), new as usize);
+                self.write_unlock();
+                unsafe { self.take(old) }
+            }
+            Err(WouldBlock) => unreachable!(),
+        }
+    }
+    /// Computes the new value from the current one under an upgradable read, so plain
+    /// readers keep going while `f` runs, then swaps it in. Returns the old value.
+    pub fn replace_with(&self, f: impl FnOnce(&T) -> T) -> T {
+        let guard = self.upgradable_read();
+        let new = RefCountedData::new::<L>(f(&guard));
+        let write = guard.upgrade();
+        let word = self.word().load(Ordering::Relaxed);
+        let old = self.data(word);
+        L::set_addr(&self.inner, L::addr(&self.inner, word), new as usize);
+        drop(write);
+        unsafe { self.take(old) }
+    }
+    /// Holds the write lock until the guard is dropped.
+    pub fn write_guard(&self) -> SpinWriteGuard<'_, T, L> {
+        match self.lock_write(Patience::Forever) {
+            Ok(word) => SpinWriteGuard::new(self, self.value_mut(word)),
+            Err(WouldBlock) => unreachable!(),
+        }
+    }
+    #[inline(always)]
+    fn write_locked<R>(&self, word: usize, f: impl FnOnce(&mut T) -> R) -> R {
+        // Unlocks (and poisons) on drop, also when `f` panics.
+        let mut guard = SpinWriteGuard::new(self, self.value_mut(word));
+        f(&mut guard)
+    }
+}
+
+pub struct SpinReadGuard<'a, T, L: CountLayout = Packed> {
+    cell: &'a SpinCell<T, L>,
+    data: NonNull<T>,
+}
+
+impl<T, L: CountLayout> Deref for SpinReadGuard<'_, T, L> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<T, L: CountLayout> Drop for SpinReadGuard<'_, T, L> {
+    fn drop(&mut self) {
+        self.cell.read_unlock();
+    }
+}
+
+unsafe impl<T: Sync, L: CountLayout> Sync for SpinReadGuard<'_, T, L> {}
+
+pub struct SpinUpgradableGuard<'a, T, L: CountLayout = Packed> {
+    cell: &'a SpinCell<T, L>,
+    data: NonNull<T>,
+}
+
+impl<'a, T, L: CountLayout> SpinUpgradableGuard<'a, T, L> {
+    pub fn upgrade(self) -> SpinWriteGuard<'a, T, L> {
+        let this = ManuallyDrop::new(self);
+        this.cell.upgrade_lock();
+        let word = this.cell.word().load(Ordering::Relaxed);
+        SpinWriteGuard::new(this.cell, this.cell.value_mut(word))
+    }
+}
+
+impl<T, L: CountLayout> Deref for SpinUpgradableGuard<'_, T, L> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<T, L: CountLayout> Drop for SpinUpgradableGuard<'_, T, L> {
+    fn drop(&mut self) {
+        self.cell
+            .word()
+            .fetch_sub(L::UPGRADER + 1, Ordering::Release);
+        self.cell.unpark();
+    }
+}
+
+unsafe impl<T: Sync, L: CountLayout> Sync for SpinUpgradableGuard<'_, T, L> {}
+
+pub struct SpinWriteGuard<'a, T, L: CountLayout = Packed> {
+    cell: &'a SpinCell<T, L>,
+    data: NonNull<T>,
+    // Poisoning is enabled and the thread was not already panicking when the lock was taken.
+    poison: bool,
+}
+
+impl<'a, T, L: CountLayout> SpinWriteGuard<'a, T, L> {
+    fn new(cell: &'a SpinCell<T, L>, data: NonNull<T>) -> Self {
+        Self {
+            cell,
+            data,
+            poison: cell.poisoning && !panicking(),
+        }
+    }
 }
 
-impl<T> Drop for SpinCell<T> {
+impl<T, L: CountLayout> Deref for SpinWriteGuard<'_, T, L> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<T, L: CountLayout> DerefMut for SpinWriteGuard<'_, T, L> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.data.as_mut() }
+    }
+}
+
+impl<T, L: CountLayout> Drop for SpinWriteGuard<'_, T, L> {
+    fn drop(&mut self) {
+        if self.poison && panicking() {
+            self.cell.poisoned.store(true, Ordering::Relaxed);
+        }
+        self.cell.write_unlock();
+    }
+}
+
+unsafe impl<T: Sync, L: CountLayout> Sync for SpinWriteGuard<'_, T, L> {}
+
+impl<T, L: CountLayout> Drop for SpinCell<T, L> {
     #[inline(always)]
     fn drop(&mut self) {
-        let current = self.inner.load(Ordering::Acquire);
-        let (addr, readers) = Self::unpack(current);
-        debug_assert_eq!(readers, 0);
-        let ptr = unsafe { NonNull::new_unchecked(addr as *mut RefCountedData<T>) };
-        drop(unsafe { Box::from_raw(ptr.as_ptr()) })
+        let current = self.word().load(Ordering::Acquire);
+        debug_assert_eq!(Self::readers(current), 0);
+        drop(unsafe { self.take(self.data(current)) })
+    }
+}
+
+unsafe impl<T: Send, L: CountLayout> Send for SpinCell<T, L> {}
+unsafe impl<T: Send + Sync, L: CountLayout> Sync for SpinCell<T, L> {}
+
+/// Like `std::sync::Mutex`, shows `<locked>` instead of waiting for a writer.
+impl<T: fmt::Debug, L: CountLayout> fmt::Debug for SpinCell<T, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SpinCell");
+        if self
+            .try_read(|v| {
+                d.field("value", v);
+            })
+            .is_err()
+        {
+            d.field("value", &format_args!("<locked>"));
+        }
+        d.field("poisoned", &self.is_poisoned()).finish()
+    }
+}
+
+impl<T: Default, L: CountLayout> Default for SpinCell<T, L> {
+    fn default() -> Self {
+        Self::with_layout(T::default())
     }
 }
 
-unsafe impl<T: Send> Send for SpinCell<T> {}
-unsafe impl<T: Send + Sync> Sync for SpinCell<T> {}
+impl<T, L: CountLayout> From<T> for SpinCell<T, L> {
+    fn from(value: T) -> Self {
+        Self::with_layout(value)
+    }
+}
+
+/// A new cell holding a snapshot of the value, with the same settings.
+impl<T: Clone, L: CountLayout> Clone for SpinCell<T, L> {
+    fn clone(&self) -> Self {
+        Self::with_layout(self.read(T::clone))
+            .fairness(self.fairness)
+            .wait_strategy(self.wait)
+            .poisoning(self.poisoning)
+    }
+}
+
+/// Compares snapshots of the two values. The cells are read-locked in address order, so
+/// two threads comparing them both ways cannot deadlock behind waiting writers.
+impl<T: PartialEq, L: CountLayout> PartialEq for SpinCell<T, L> {
+    fn eq(&self, other: &Self) -> bool {
+        if ptr::eq(self, other) {
+            // A second read lock could wait behind a writer that waits for the first.
+            #[allow(clippy::eq_op)]
+            return self.read(|v| v == v);
+        }
+        let (first, second) = if (self as *const Self) < (other as *const Self) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        first.read(|x| second.read(|y| x == y))
+    }
+}
+
+/// Serializes a snapshot taken under a read lock.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, L: CountLayout> serde::Serialize for SpinCell<T, L> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.read(|v| v.serialize(serializer))
+    }
+}
+
+/// Builds a cell with the default fairness and wait strategy.
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>, L: CountLayout> serde::Deserialize<'de> for SpinCell<T, L> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::with_layout)
+    }
+}